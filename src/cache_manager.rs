@@ -0,0 +1,513 @@
+//! Finds, describes and removes the frame buffer caches in the user cache directory, and 
+//! keeps an index of when each cache was last used so the least recently used ones can be 
+//! evicted once the caches grow past a size cap.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::paths;
+
+const INDEX_FILE: &str = "index.toml";
+// the temporary file of a render that wasn't written to for this long was left behind by 
+// an interrupted render
+const STALE_RENDER_AGE: Duration = Duration::from_secs(60 * 60);
+
+const INDEX_LOCK_FILE: &str = "index.lock";
+
+// the index is read, modified and written back by both the playback and the resize renders
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Exclusive access to the index, both within this process and against other mofetch 
+/// processes, e.g. two terminals opened at once or a `cache warm` next to a shell startup. 
+/// Released when dropped.
+struct IndexLock {
+    _thread: MutexGuard<'static, ()>,
+    _file: Option<std::fs::File>,
+}
+
+fn lock_index() -> IndexLock {
+    let thread = INDEX_LOCK.lock().unwrap();
+    // the lock file is only advisory. Without a writable cache directory there is no 
+    // index to write either, so the threads are still serialized
+    let file = std::fs::File::options().create(true).truncate(false).write(true)
+        .open(paths::cache_dir().join(INDEX_LOCK_FILE)).ok()
+        .filter(|file| file.lock().is_ok());
+    return IndexLock { _thread: thread, _file: file };
+}
+
+/// What is known about a cache beyond its file: the source and settings it was rendered 
+/// from, and when it was last played. Stored in `index.toml` next to the caches.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct IndexEntry {
+    /// file name of the cache in the cache directory
+    pub file: String,
+    pub source: String,
+    pub settings: String,
+    pub cols: u32,
+    pub rows: u32,
+    pub frames: usize,
+    pub fps: f64,
+    /// unix timestamps in seconds
+    pub created: u64,
+    pub last_used: u64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct CacheIndex {
+    #[serde(default)]
+    entries: Vec<IndexEntry>,
+}
+
+/// A size in bytes, written like `500MiB`, `2G` or `1024`. Units are powers of 1024.
+#[derive(Clone, Copy)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let multiplier: u64 = match unit.trim() {
+            "" | "B" => 1,
+            "K" | "KB" | "KiB" => 1 << 10,
+            "M" | "MB" | "MiB" => 1 << 20,
+            "G" | "GB" | "GiB" => 1 << 30,
+            _ => return Err(format!("unknown size unit in \"{}\" (expected B, KiB, MiB or GiB)", s)),
+        };
+        return match number.trim().parse::<f64>() {
+            Ok(number) if number >= 0.0 => Ok(ByteSize((number * multiplier as f64) as u64)),
+            _ => Err(format!("invalid size \"{}\" (e.g. 500MiB or 2GiB)", s)),
+        };
+    }
+}
+
+/// An age written like `30d`, `12h`, `45m`, `90s` or `2w`
+#[derive(Clone, Copy)]
+pub struct Age(pub Duration);
+
+impl std::str::FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid age \"{}\" (e.g. 30d, 12h or 2w)", s);
+        let split = s.char_indices().last().map(|(i, _)| i).unwrap_or(0);
+        let (number, unit) = s.split_at(split);
+        let seconds: u64 = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => return Err(error()),
+        };
+        let number = number.parse::<u64>().map_err(|_| error())?;
+        let seconds = number.checked_mul(seconds).ok_or_else(|| format!("age \"{}\" is too long", s))?;
+        return Ok(Age(Duration::from_secs(seconds)));
+    }
+}
+
+/// One cached thumbnail. Renders for other terminal sizes (`--reprocess-on-resize`) are
+/// separate entries of the same input, with their terminal size as `variant`.
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub input: String,
+    pub variant: Option<(u16,u16)>,
+    pub is_image: bool,
+    pub size: u64,
+    /// from the index, or the file modification time for caches that aren't indexed
+    pub last_used: SystemTime,
+    pub record: Option<IndexEntry>,
+    /// the temporary file of a render that is still running, or was interrupted
+    pub partial: bool,
+}
+
+/// Every cache in the cache directory, including the temporary files of unfinished 
+/// renders, sorted by input
+pub fn entries() -> Vec<CacheEntry> {
+    let mut entries = Vec::new();
+    let Ok(dir) = std::fs::read_dir(paths::cache_dir()) else {
+        return entries;
+    };
+    let index = {
+        let _lock = lock_index();
+        load_index()
+    };
+    for file in dir.flatten() {
+        let path = file.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let (name, partial) = match name.strip_suffix(".tmp") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let Some((stem, extension)) = name.rsplit_once('.') else {
+            continue;
+        };
+        let is_image = match extension {
+            "afb" => false,
+            "sfb" => true,
+            _ => continue,
+        };
+        let (input, variant) = split_variant(stem);
+        let metadata = file.metadata().ok();
+        // the index only describes finished caches
+        let record = index.entries.iter().find(|record| !partial && record.file == name);
+        let last_used = match record {
+            Some(record) => UNIX_EPOCH + Duration::from_secs(record.last_used),
+            None => metadata.as_ref().and_then(|metadata| metadata.modified().ok()).unwrap_or(UNIX_EPOCH),
+        };
+        entries.push(CacheEntry {
+            input: String::from(input),
+            variant,
+            is_image,
+            size: metadata.map(|metadata| metadata.len()).unwrap_or(0),
+            last_used,
+            record: record.cloned(),
+            partial,
+            path,
+        });
+    }
+    entries.sort_by(|a, b| (&a.input, a.variant).cmp(&(&b.input, b.variant)));
+    return entries;
+}
+
+/// Splits a cache stem like `input.gif@120x40` into the input name and the terminal size
+fn split_variant(stem: &str) -> (&str, Option<(u16,u16)>) {
+    if let Some((input, size)) = stem.rsplit_once('@')
+        && let Some((cols, rows)) = size.split_once('x')
+        && let (Ok(cols), Ok(rows)) = (cols.parse(), rows.parse()) {
+        return (input, Some((cols, rows)));
+    }
+    return (stem, None);
+}
+
+/// Frame size in characters, frame count and fps of a cache. These come from the index, 
+/// or from reading the cache when it isn't indexed.
+pub fn stats(entry: &CacheEntry) -> (u32, u32, usize, f64) {
+    if let Some(record) = &entry.record {
+        return (record.cols, record.rows, record.frames, record.fps);
+    }
+    // unreadable or broken caches are listed without stats
+    return match crate::frame_cache::CacheReader::open(&entry.path) {
+        Ok(cache) => (cache.info().cols, cache.info().rows, cache.frame_count(), cache.info().fps),
+        Err(_) => (0, 0, 0, 0.0),
+    };
+}
+
+/// How long ago `time` was, e.g. `5m ago` or `3d ago`
+pub fn format_age(time: SystemTime) -> String {
+    let seconds = SystemTime::now().duration_since(time).unwrap_or_default().as_secs();
+    return match seconds {
+        0..60 => String::from("just now"),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    };
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    return if unit == 0 {format!("{} {}", bytes, units[0])} else {format!("{:.1} {}", size, units[unit])};
+}
+
+pub fn variant_name(entry: &CacheEntry) -> String {
+    return match entry.variant {
+        Some((cols, rows)) => format!("{}x{}", cols, rows),
+        None => String::from("-"),
+    };
+}
+
+/// Removes every cache of `input`, including its size variants. Returns the number of
+/// removed caches.
+pub fn remove_input(input: &str) -> usize {
+    let removed: Vec<CacheEntry> = entries().into_iter().filter(|entry| entry.input == input).collect();
+    return remove_entries(&removed);
+}
+
+/// Removes caches and their index entries. Returns the number of removed caches.
+fn remove_entries(removed: &[CacheEntry]) -> usize {
+    let mut count = 0;
+    for entry in removed.iter() {
+        if std::fs::remove_file(&entry.path).is_ok() {
+            count += 1;
+        }
+    }
+
+    let _lock = lock_index();
+    let mut index = load_index();
+    index.entries.retain(|record| !removed.iter().any(|entry| entry.record.as_ref().is_some_and(|r| r.file == record.file)));
+    save_index(&index);
+    return count;
+}
+
+/// Removes the temporary files of interrupted renders, the caches not used within 
+/// `older_than`, then the least recently used caches until all of them together fit in 
+/// `max_size`. The caches in `keep` and renders that are still running are never removed, 
+/// but do count towards the size. Returns the removed caches.
+pub fn prune(max_size: Option<u64>, older_than: Option<Duration>, keep: &[&Path]) -> Vec<CacheEntry> {
+    let removed = select_pruned(entries(), SystemTime::now(), max_size, older_than, keep);
+    remove_entries(&removed);
+    return removed;
+}
+
+/// The caches `prune` removes out of `entries` at the time `now`
+fn select_pruned(entries: Vec<CacheEntry>, now: SystemTime, max_size: Option<u64>, older_than: Option<Duration>, keep: &[&Path]) -> Vec<CacheEntry> {
+    // a running render writes its temporary file with every frame
+    let stale_cutoff = now.checked_sub(STALE_RENDER_AGE).unwrap_or(UNIX_EPOCH);
+    let (mut removed, entries): (Vec<CacheEntry>, Vec<CacheEntry>) = entries.into_iter()
+        .partition(|entry| entry.partial && entry.last_used < stale_cutoff);
+    let (kept, mut entries): (Vec<CacheEntry>, Vec<CacheEntry>) = entries.into_iter()
+        .partition(|entry| entry.partial || keep.iter().any(|keep| *keep == entry.path));
+    entries.sort_by_key(|entry| entry.last_used);
+
+    if let Some(older_than) = older_than {
+        // an age from before the epoch keeps every cache
+        let cutoff = now.checked_sub(older_than).unwrap_or(UNIX_EPOCH);
+        let (old, recent): (Vec<CacheEntry>, Vec<CacheEntry>) = entries.into_iter().partition(|entry| entry.last_used < cutoff);
+        removed.extend(old);
+        entries = recent;
+    }
+    if let Some(max_size) = max_size {
+        let kept_size: u64 = kept.iter().map(|entry| entry.size).sum();
+        let mut total: u64 = kept_size + entries.iter().map(|entry| entry.size).sum::<u64>();
+        let mut entries = entries.into_iter();
+        while total > max_size && let Some(entry) = entries.next() {
+            total -= entry.size;
+            removed.push(entry);
+        }
+    }
+    return removed;
+}
+
+fn now() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+}
+
+fn load_index() -> CacheIndex {
+    let path = paths::cache_dir().join(INDEX_FILE);
+    return std::fs::read_to_string(path).ok()
+        .and_then(|index| toml::from_str(&index).ok())
+        .unwrap_or_default();
+}
+
+fn save_index(index: &CacheIndex) {
+    let path = paths::cache_dir().join(INDEX_FILE);
+    let Ok(index) = toml::to_string(index) else {
+        return;
+    };
+    // written to a temporary file first, like the caches
+    let tmp_path = paths::with_suffix(&path, ".tmp");
+    if std::fs::write(&tmp_path, index).is_ok() {
+        std::fs::rename(&tmp_path, &path).ok();
+    }
+}
+
+/// Adds a freshly rendered cache to the index, replacing the entry of an earlier render
+pub fn record_render(mut record: IndexEntry) {
+    let _lock = lock_index();
+    let mut index = load_index();
+    index.entries.retain(|entry| entry.file != record.file);
+    record.created = now();
+    record.last_used = record.created;
+    index.entries.push(record);
+    save_index(&index);
+}
+
+/// The source of a cache as it is recorded in the index: the canonical path of the input, 
+/// so the same file is recognized from any directory. Inputs that aren't files, e.g. URLs, 
+/// are recorded as given.
+pub fn source_key(input: &str) -> String {
+    return std::fs::canonicalize(input).ok()
+        .and_then(|path| path.to_str().map(String::from))
+        .unwrap_or_else(|| String::from(input));
+}
+
+/// The index entry of a cache
+fn find_record(cache_path: &Path) -> Option<IndexEntry> {
+    let file = cache_path.file_name()?.to_str()?;
+    let _lock = lock_index();
+    return load_index().entries.into_iter().find(|entry| entry.file == file);
+}
+
+/// Whether a cache has to be rendered again for `input`. Caches are named after the file 
+/// name of their input, so the cache may be of another file with the same name, e.g. 
+/// `~/b/intro.mp4` when playing `~/a/intro.mp4`, or it was rendered with other settings, 
+/// e.g. before its profile was edited. Caches that aren't indexed are kept.
+pub fn is_stale(cache_path: &Path, input: &str, settings: &str) -> bool {
+    return find_record(cache_path).is_some_and(|record| record_is_stale(&record, input, settings));
+}
+
+fn record_is_stale(record: &IndexEntry, input: &str, settings: &str) -> bool {
+    return record.source != source_key(input) || record.settings != settings;
+}
+
+/// Marks a cache as used by playback
+pub fn touch(cache_path: &Path) {
+    let Some(file) = cache_path.file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+    let _lock = lock_index();
+    let mut index = load_index();
+    if let Some(entry) = index.entries.iter_mut().find(|entry| entry.file == file) {
+        entry.last_used = now();
+        save_index(&index);
+    }
+}
+
+/// Removes every cache, their temporary files and the index. The directory itself and 
+/// anything else in it are left alone, as the cache directory can be set to any folder. 
+/// The lock file of the index is kept too, as another process may be holding it.
+pub fn clear() -> std::io::Result<()> {
+    for entry in entries() {
+        remove_existing(&entry.path)?;
+        remove_existing(&paths::with_suffix(&entry.path, ".tmp"))?;
+    }
+    let _lock = lock_index();
+    return remove_existing(&paths::cache_dir().join(INDEX_FILE));
+}
+
+/// Removes a file, if there is one
+fn remove_existing(path: &Path) -> std::io::Result<()> {
+    return match std::fs::remove_file(path) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_size() {
+        let cases = [
+            ("1024", 1024),
+            ("0", 0),
+            ("12B", 12),
+            ("2K", 2 << 10),
+            ("2KB", 2 << 10),
+            ("500MiB", 500 << 20),
+            ("500 MiB", 500 << 20),
+            ("1.5G", 3 << 29),
+            ("2GiB", 2 << 30),
+        ];
+        for (text, bytes) in cases {
+            assert_eq!(text.parse::<ByteSize>().map(|size| size.0), Ok(bytes), "{}", text);
+        }
+        for text in ["", "MiB", "-1G", "5T", "5 mib", "1.2.3K", "5µ"] {
+            assert!(text.parse::<ByteSize>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn age() {
+        let cases = [
+            ("90s", 90),
+            ("45m", 45 * 60),
+            ("12h", 12 * 60 * 60),
+            ("30d", 30 * 24 * 60 * 60),
+            ("2w", 2 * 7 * 24 * 60 * 60),
+            ("0d", 0),
+        ];
+        for (text, seconds) in cases {
+            assert_eq!(text.parse::<Age>().map(|age| age.0), Ok(Duration::from_secs(seconds)), "{}", text);
+        }
+        for text in ["", "d", "30", "30y", "-1d", "1.5h", "30 d", "5µ", "µ", "99999999999999999999s"] {
+            assert!(text.parse::<Age>().is_err(), "{}", text);
+        }
+        assert_eq!(format!("{}w", u64::MAX / 1000).parse::<Age>().err().unwrap(), format!("age \"{}w\" is too long", u64::MAX / 1000));
+    }
+
+    fn entry(name: &str, size: u64, age: u64, now: SystemTime) -> CacheEntry {
+        let (name, partial) = match name.strip_suffix(".tmp") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        return CacheEntry {
+            path: PathBuf::from(format!("/cache/{}{}", name, if partial {".tmp"} else {""})),
+            input: String::from(name),
+            variant: None,
+            is_image: false,
+            size,
+            last_used: now - Duration::from_secs(age),
+            record: None,
+            partial,
+        };
+    }
+
+    fn pruned(max_size: Option<u64>, older_than: Option<Duration>, keep: &[&str]) -> Vec<String> {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let entries = vec![
+            entry("new.afb", 100, 10, now),
+            entry("old.afb", 100, 5000, now),
+            entry("oldest.afb", 100, 9000, now),
+            entry("middle.afb", 100, 1000, now),
+            entry("running.afb.tmp", 50, 60, now),
+            entry("interrupted.afb.tmp", 50, 2 * 60 * 60, now),
+        ];
+        let keep: Vec<PathBuf> = keep.iter().map(|name| PathBuf::from(format!("/cache/{}", name))).collect();
+        let keep: Vec<&Path> = keep.iter().map(|path| path.as_path()).collect();
+        return select_pruned(entries, now, max_size, older_than, &keep).into_iter()
+            .map(|entry| entry.input).collect();
+    }
+
+    #[test]
+    fn prune_order() {
+        // interrupted renders go first, then the least recently used caches
+        assert_eq!(pruned(None, None, &[]), ["interrupted.afb"]);
+        assert_eq!(pruned(Some(1000), None, &[]), ["interrupted.afb"]);
+        assert_eq!(pruned(Some(300), None, &[]), ["interrupted.afb", "oldest.afb", "old.afb"]);
+        assert_eq!(pruned(Some(0), None, &[]), ["interrupted.afb", "oldest.afb", "old.afb", "middle.afb", "new.afb"]);
+        assert_eq!(pruned(None, Some(Duration::from_secs(2000)), &[]), ["interrupted.afb", "oldest.afb", "old.afb"]);
+        assert_eq!(pruned(Some(200), Some(Duration::from_secs(6000)), &[]), ["interrupted.afb", "oldest.afb", "old.afb", "middle.afb"]);
+        // an age from before the epoch keeps every cache
+        assert_eq!(pruned(None, Some(Duration::MAX), &[]), ["interrupted.afb"]);
+    }
+
+    #[test]
+    fn same_file_name() {
+        let dir = std::env::temp_dir().join(format!("mofetch-test-{}-same-name", std::process::id()));
+        let (a, b) = (dir.join("a").join("intro.mp4"), dir.join("b").join("intro.mp4"));
+        for input in [&a, &b] {
+            std::fs::create_dir_all(input.parent().unwrap()).unwrap();
+            std::fs::write(input, "").unwrap();
+        }
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+        let record = IndexEntry {
+            file: String::from("intro.mp4.afb"),
+            source: source_key(a),
+            settings: String::from("fit"),
+            cols: 0,
+            rows: 0,
+            frames: 0,
+            fps: 0.0,
+            created: 0,
+            last_used: 0,
+        };
+        // both inputs have the cache intro.mp4.afb
+        let stale = (record_is_stale(&record, a, "fit"), record_is_stale(&record, b, "fit"), record_is_stale(&record, a, "cover"));
+        let via_parent = dir.join("b").join("..").join("a").join("intro.mp4");
+        let same_file = record_is_stale(&record, via_parent.to_str().unwrap(), "fit");
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(stale, (false, true, true));
+        assert!(!same_file);
+    }
+
+    #[test]
+    fn prune_keep() {
+        // kept caches and running renders are never removed, but count towards the size
+        assert_eq!(pruned(Some(300), None, &["oldest.afb"]), ["interrupted.afb", "old.afb", "middle.afb"]);
+        assert_eq!(pruned(Some(0), None, &["oldest.afb", "new.afb"]), ["interrupted.afb", "old.afb", "middle.afb"]);
+        assert_eq!(pruned(None, Some(Duration::from_secs(0)), &["oldest.afb"]), ["interrupted.afb", "old.afb", "middle.afb", "new.afb"]);
+        // interrupted renders are removed even when they are to be kept
+        assert_eq!(pruned(Some(150), None, &["interrupted.afb.tmp"]), ["interrupted.afb", "oldest.afb", "old.afb", "middle.afb"]);
+    }
+}
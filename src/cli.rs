@@ -0,0 +1,816 @@
+//! Command line parsing. Commands and options are declared once in `help_options`, and the
+//! parser, the help text and the shell completions are all built from those declarations.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use lexopt::Arg::{Long, Short, Value};
+use lexopt::ValueExt;
+use crate::help_options::{self, CommandHelp, HelpOption, OptionGroup};
+use crate::fetch::{self, config_manager::OptionsDefaults};
+
+pub enum Command {
+    Show(OptionsDefaults),
+    Render(OptionsDefaults),
+    Cache(CacheCommand),
+    Gpus,
+    Config(ConfigCommand),
+    Completions(Shell),
+}
+
+pub enum CacheCommand {
+    Ls,
+    Rm(String),
+    Clear,
+    Info(String),
+    Warm(String),
+    Prune {
+        max_size: Option<u64>,
+        older_than: Option<std::time::Duration>,
+    },
+}
+
+pub enum ConfigCommand {
+    Init,
+    Path,
+    Edit,
+    Validate,
+}
+
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl std::str::FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("unknown shell \"{}\" (expected bash, zsh or fish)", s)),
+        };
+    }
+}
+
+pub fn parse_args() -> Result<Command, lexopt::Error> {
+    let commands = help_options::init_commands();
+
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    take_path_options(&mut args)?;
+
+    // without a command, the arguments are those of `show`
+    let (name, implicit) = match args.first().and_then(|arg| arg.to_str()) {
+        Some(first) if first == "help" || find_command(&commands, first).is_some() => {
+            (args.remove(0).into_string().unwrap(), false)
+        }
+        _ => (String::from("show"), true),
+    };
+    let mut parser = lexopt::Parser::from_args(args.clone());
+
+    if name == "help" {
+        let topic = parse_positionals(&mut parser, None)?;
+        let command = topic.first().and_then(|topic| find_command(&commands, topic));
+        print_help(command);
+        std::process::exit(0);
+    }
+
+    let command = find_command(&commands, &name).unwrap();
+    return match name.as_str() {
+        "show" => Ok(Command::Show(parse_options(args, command, implicit)?)),
+        "render" => Ok(Command::Render(parse_options(args, command, false)?)),
+        "cache" => Ok(Command::Cache(parse_cache(&mut parser, command)?)),
+        "gpus" => {
+            let args = parse_positionals(&mut parser, Some(command))?;
+            if !args.is_empty() {
+                return Err(usage_error(command));
+            }
+            Ok(Command::Gpus)
+        }
+        "config" => {
+            let args = parse_positionals(&mut parser, Some(command))?;
+            match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+                ["init"] => Ok(Command::Config(ConfigCommand::Init)),
+                ["path"] => Ok(Command::Config(ConfigCommand::Path)),
+                ["edit"] => Ok(Command::Config(ConfigCommand::Edit)),
+                ["validate"] => Ok(Command::Config(ConfigCommand::Validate)),
+                _ => Err(usage_error(command)),
+            }
+        }
+        "completions" => {
+            let args = parse_positionals(&mut parser, Some(command))?;
+            match args.as_slice() {
+                [shell] => Ok(Command::Completions(shell.parse().map_err(lexopt::Error::from)?)),
+                _ => Err(usage_error(command)),
+            }
+        }
+        _ => unreachable!(),
+    };
+}
+
+/// Takes `--config`, `--cache-dir` and `--data-dir` out of the arguments. They apply to every command, and
+/// the config they point to is read before the other options are parsed.
+fn take_path_options(args: &mut Vec<OsString>) -> Result<(), lexopt::Error> {
+    let mut config_path = None;
+    let mut cache_dir = None;
+    let mut data_dir = None;
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].to_string_lossy().into_owned();
+        if arg == "--" {
+            break;
+        }
+        let Some(name) = ["config", "cache-dir", "data-dir"].into_iter()
+            .find(|name| arg == format!("--{}", name) || arg.starts_with(&format!("--{}=", name))) else {
+            index += 1;
+            continue;
+        };
+        // lexopt splits off the value, which may not be valid UTF-8
+        let mut parser = lexopt::Parser::from_args(args[index..].iter().take(2).cloned());
+        parser.next()?;
+        let value = PathBuf::from(parser.value()?);
+        args.drain(index..if arg.contains('=') {index + 1} else {index + 2});
+        match name {
+            "config" => config_path = Some(value),
+            "cache-dir" => cache_dir = Some(value),
+            _ => data_dir = Some(value),
+        }
+    }
+    crate::paths::set_overrides(config_path, cache_dir, data_dir);
+    return Ok(());
+}
+
+fn find_command<'a>(commands: &'a [CommandHelp], name: &str) -> Option<&'a CommandHelp> {
+    return commands.iter().find(|command| command.name.as_deref() == Some(name));
+}
+
+fn usage_error(command: &CommandHelp) -> lexopt::Error {
+    return lexopt::Error::from(format!("Usage: {}", usage(command)));
+}
+
+fn usage(command: &CommandHelp) -> String {
+    let mut usage = format!("mofetch {}", command.name.as_ref().unwrap());
+    if !command.groups.is_empty() {
+        usage += " [options]";
+    }
+    if let Some(args) = &command.args {
+        usage += &format!(" {}", args);
+    }
+    return usage;
+}
+
+/// The options of every group `command` accepts
+fn command_options<'a>(groups: &'a [OptionGroup], command: &CommandHelp) -> Vec<&'a HelpOption> {
+    return groups.iter()
+        .filter(|group| command.groups.contains(group.name.as_ref().unwrap()))
+        .flat_map(|group| group.options.iter())
+        .collect();
+}
+
+/// Collects the plain arguments of a command that takes no options
+fn parse_positionals(parser: &mut lexopt::Parser, command: Option<&CommandHelp>) -> Result<Vec<String>, lexopt::Error> {
+    let mut args = Vec::new();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('h') | Short('?') | Long("help") => {
+                print_help(command);
+                std::process::exit(0);
+            }
+            Value(value) => args.push(value.string()?),
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    return Ok(args);
+}
+
+fn parse_cache(parser: &mut lexopt::Parser, command: &CommandHelp) -> Result<CacheCommand, lexopt::Error> {
+    let groups = help_options::init_options();
+    let options = command_options(&groups, command);
+
+    let mut args = Vec::new();
+    let mut max_size = None;
+    let mut older_than = None;
+    while let Some(arg) = parser.next()? {
+        let long = match &arg {
+            Short('h') | Short('?') | Long("help") => {
+                print_help(Some(command));
+                std::process::exit(0);
+            }
+            Value(value) => {
+                args.push(value.clone().string()?);
+                continue;
+            }
+            Short(_) => None,
+            Long(long) => options.iter()
+                .find(|option| option.long.as_deref() == Some(*long))
+                .and_then(|option| option.long.clone()),
+        };
+        match long.as_deref() {
+            Some("max-size") => {
+                max_size = Some(parser.value()?.parse::<crate::cache_manager::ByteSize>()?.0);
+            }
+            Some("older-than") => {
+                older_than = Some(parser.value()?.parse::<crate::cache_manager::Age>()?.0);
+            }
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    let has_options = max_size.is_some() || older_than.is_some();
+    return match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["prune"] => Ok(CacheCommand::Prune { max_size, older_than }),
+        _ if has_options => Err(lexopt::Error::from("--max-size and --older-than only apply to \"mofetch cache prune\"")),
+        ["ls"] => Ok(CacheCommand::Ls),
+        ["rm", input] => Ok(CacheCommand::Rm(input.to_string())),
+        ["clear"] => Ok(CacheCommand::Clear),
+        ["info", input] => Ok(CacheCommand::Info(input.to_string())),
+        ["warm", dir] => Ok(CacheCommand::Warm(dir.to_string())),
+        _ => Err(usage_error(command)),
+    };
+}
+
+/// Parses the options of `show` and `render` on top of the config defaults, or the ones of 
+/// the profile picked with `--profile` or by `[inputs]`. `implicit` is set when no command 
+/// was given, in which case --help shows the general help.
+fn parse_options(args: Vec<OsString>, command: &CommandHelp, implicit: bool) -> Result<OptionsDefaults, lexopt::Error> {
+    // the profile depends on the arguments, so they are parsed once to find it and again 
+    // on top of the options of the profile
+    let defaults = fetch::get_config_defaults(None);
+    let mut config = apply_options(&mut lexopt::Parser::from_args(args.clone()), defaults, command, implicit)?;
+
+    // an input given on the command line is played instead of a random one, even when it
+    // is the input of the config
+    let input = if config.random && !config.input_given {
+        let Some(input_dir) = &config.input_dir else {
+            println!("Error: --random picks the input from a directory, set one with --input-dir or input_dir in the config");
+            std::process::exit(0);
+        };
+        let input = crate::core::random_media_file(&fetch::config_manager::expand_home(input_dir)).unwrap_or_else(|error| {
+            println!("Error: {}", error);
+            std::process::exit(0);
+        });
+        Some(input)
+    }
+    else if let Some(input) = &config.input {
+        let input = fetch::config_manager::expand_home(input);
+        let Some(input) = input.to_str() else {
+            println!("Error: {} is not a valid UTF-8 path", input.display());
+            std::process::exit(0);
+        };
+        Some(String::from(input))
+    }
+    else {
+        None
+    };
+
+    let profile = config.profile.clone().or_else(|| {
+        return fetch::config_manager::input_profile(&fetch::config_manager::retrieve_config(None), input.as_ref()?);
+    });
+    if let Some(profile) = profile {
+        let defaults = fetch::get_config_defaults(Some(&profile));
+        config = apply_options(&mut lexopt::Parser::from_args(args), defaults, command, implicit)?;
+    }
+    config.input = input;
+
+    if config.input.is_none() {
+        println!("Error: Expected file input. Use \"mofetch --help\" for usage help");
+        std::process::exit(0);
+    }
+    if config.gamma <= 0.0 {
+        println!("Error: --gamma must be greater than 0");
+        std::process::exit(0);
+    }
+    if config.kernel_radius.is_some_and(|radius| radius > crate::core::MAX_KERNEL_RADIUS) {
+        println!("Error: --kernel-radius can be at most {}", crate::core::MAX_KERNEL_RADIUS);
+        std::process::exit(0);
+    }
+    if config.speed <= 0.0 {
+        println!("Error: --speed must be greater than 0");
+        std::process::exit(0);
+    }
+    if config.loops == Some(0) {
+        println!("Error: --loops must be greater than 0");
+        std::process::exit(0);
+    }
+
+    return Ok(config);
+}
+
+/// Applies the arguments to `config`. Short flags are resolved to their long name through 
+/// the option declarations.
+fn apply_options(parser: &mut lexopt::Parser, mut config: OptionsDefaults, command: &CommandHelp, implicit: bool) -> Result<OptionsDefaults, lexopt::Error> {
+    let groups = help_options::init_options();
+    let options = command_options(&groups, command);
+
+    while let Some(arg) = parser.next()? {
+        let long = match &arg {
+            Short('h') | Short('?') | Long("help") => {
+                print_help(if implicit {None} else {Some(command)});
+                std::process::exit(0);
+            }
+            Short(short) => options.iter()
+                .find(|option| option.short.as_deref() == Some(short.to_string().as_str()))
+                .and_then(|option| option.long.clone()),
+            Long(long) => options.iter()
+                .find(|option| option.long.as_deref() == Some(*long))
+                .and_then(|option| option.long.clone()),
+            Value(input) => {
+                config.input = Some(input.clone().string()?);
+                config.input_given = true;
+                continue;
+            }
+        };
+        let Some(long) = long else {
+            return Err(arg.unexpected());
+        };
+        apply_option(&mut config, &long, parser)?;
+    }
+    return Ok(config);
+}
+
+fn apply_option(config: &mut OptionsDefaults, long: &str, parser: &mut lexopt::Parser) -> Result<(), lexopt::Error> {
+    match long {
+        "verbose" => {
+            config.verbose = true;
+        }
+        "version" => {
+            println!("mofetch {}",crate::MOFETCH_VERSION);
+            std::process::exit(0);
+        }
+        "input" => {
+            config.input = Some(parser.value()?.parse()?);
+            config.input_given = true;
+        }
+        "input-dir" => {
+            config.input_dir = Some(parser.value()?.parse()?);
+        }
+        "random" => {
+            config.random = true;
+        }
+        "profile" => {
+            config.profile = Some(parser.value()?.parse()?);
+        }
+        "overwrite-cache" => {
+            config.overwrite_cache = true;
+        }
+        "fps" => {
+            config.fps = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "brightness" => {
+            config.brightness = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "contrast" => {
+            config.contrast = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "gamma" => {
+            config.gamma = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "saturation" => {
+            config.saturation = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "invert" => {
+            config.invert = true;
+            config.overwrite_cache = true;
+        }
+        "equalize" => {
+            config.equalize = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "background" => {
+            config.background = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "sharpen" => {
+            config.sharpen = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "no-edges" => {
+            config.draw_edges = false;
+            config.overwrite_cache = true;
+        }
+        "edge-threshold" => {
+            config.edge_threshold = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "charset" => {
+            config.charset = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "shape-glyphs" => {
+            config.shape_glyphs = true;
+            config.overwrite_cache = true;
+        }
+        "temporal" => {
+            config.temporal = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "temporal-strength" => {
+            config.temporal_strength = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "dither" => {
+            config.dither = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "sigma" => {
+            config.sigma = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "sigma-ratio" => {
+            config.sigma_ratio = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "kernel-radius" => {
+            config.kernel_radius = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "dog-threshold" => {
+            config.dog_threshold = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "tau" => {
+            config.tau = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "phi" => {
+            config.phi = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "edge-bin-width" => {
+            config.edge_bin_width = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "max-width" => {
+            config.max_width = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "max-height" => {
+            config.max_height = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "hide-info" => {
+            config.hide_info = true;
+        }
+        "reprocess-on-resize" => {
+            config.reprocess_on_resize = true;
+        }
+        "progressive" => {
+            config.progressive = true;
+        }
+        "progressive-frames" => {
+            config.progressive_frames = parser.value()?.parse()?;
+        }
+        "loops" => {
+            config.loops = Some(parser.value()?.parse()?);
+        }
+        "duration" => {
+            config.duration = Some(parser.value()?.parse()?);
+        }
+        "speed" => {
+            config.speed = parser.value()?.parse()?;
+        }
+        "ping-pong" => {
+            config.ping_pong = true;
+        }
+        "fit" => {
+            config.fit = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        "crop" => {
+            config.crop = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "target-cols" => {
+            config.target_cols = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "target-rows" => {
+            config.target_rows = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "start" => {
+            config.start = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "end" => {
+            config.end = Some(parser.value()?.parse()?);
+            config.overwrite_cache = true;
+        }
+        "dump-stages" => {
+            config.dump_stages = Some(PathBuf::from(parser.value()?));
+            config.overwrite_cache = true;
+        }
+        "dump-frames" => {
+            config.dump_frames = Some(parser.value()?.parse_with(|list| {
+                list.split(',').map(|n| n.trim().parse::<usize>()).collect::<Result<Vec<usize>,_>>()
+            })?);
+        }
+        "adapter-index" => {
+            config.adapter_index = parser.value()?.parse()?;
+            config.overwrite_cache = true;
+        }
+        _ => return Err(lexopt::Error::UnexpectedOption(format!("--{}", long))),
+    }
+    return Ok(());
+}
+
+/// Pads `text` to the description column of the help text
+fn help_line(text: String, desc: &str) -> String {
+    let space_count = std::cmp::max(32, text.len() + 2) - text.len();
+    let mut spaces: String = Default::default();
+    for _ in 1..space_count {
+        spaces += " ";
+    }
+    return format!("{0}{1}{2}", text, spaces, desc);
+}
+
+/// Prints the help of a command, or the general help (commands and the options of `show`)
+/// without one.
+pub fn print_help(command: Option<&CommandHelp>) {
+    let commands = help_options::init_commands();
+    match command {
+        None => {
+            let help_intro = String::from("mofetch is a system information fetching tool with fancy user-generated ASCII art");
+            let help_usage = String::from("Usage: mofetch [command] [options] [input]");
+            println!("{}",help_intro);
+            println!("{}",help_usage);
+
+            println!("\nCommands");
+            for command in commands.iter() {
+                let name = format!("   {} {}", command.name.as_ref().unwrap(), command.args.as_deref().unwrap_or(""));
+                println!("{}", help_line(name, command.desc.as_ref().unwrap()));
+            }
+            println!("\nWithout a command, mofetch runs \"show\". Use \"mofetch help <command>\" for the options of a command.");
+            print_options(find_command(&commands, "show").unwrap());
+        }
+        Some(command) if !command.subcommands.is_empty() => {
+            println!("{}", command.desc.as_ref().unwrap());
+            println!("Usage: {}", usage(command));
+            println!("\nCommands");
+            for subcommand in command.subcommands.iter() {
+                let name = format!("   {} {}", subcommand.name.as_ref().unwrap(), subcommand.args.as_deref().unwrap_or(""));
+                println!("{}", help_line(name, subcommand.desc.as_ref().unwrap()));
+            }
+            print_options(command);
+        }
+        Some(command) => {
+            println!("{}", command.desc.as_ref().unwrap());
+            println!("Usage: {}", usage(command));
+            print_options(command);
+        }
+    }
+}
+
+fn print_options(command: &CommandHelp) {
+    if command.groups.is_empty() {
+        return;
+    }
+    if command.groups.contains(&String::from("Pre-processing options")) {
+        println!("\nNOTE: Specifying pre-processing or shader options will force --overwrite-cache.");
+    }
+
+    let groups = help_options::init_options();
+    for group in groups.iter().filter(|group| command.groups.contains(group.name.as_ref().unwrap())) {
+        println!("\n{}",group.name.as_ref().unwrap());
+        for option in group.options.iter() {
+            let short = if option.short.is_some() {
+                format!("-{}",option.short.as_ref().unwrap())
+            } else {
+                String::from("  ")
+            };
+            let datatype = if option.datatype.is_some() {
+                format!(" <{}>",option.datatype.as_ref().unwrap())
+            } else {
+                String::from("")
+            };
+            let pre_desc_text = format!("{0} --{2}{1}",short,datatype,option.long.as_ref().unwrap());
+            println!("{}", help_line(pre_desc_text, option.desc.as_ref().unwrap()));
+        }
+    }
+}
+
+pub fn print_completions(shell: &Shell) {
+    let commands = help_options::init_commands();
+    let groups = help_options::init_options();
+    let script = match shell {
+        Shell::Bash => bash_completions(&commands, &groups),
+        Shell::Zsh => zsh_completions(&commands, &groups),
+        Shell::Fish => fish_completions(&commands, &groups),
+    };
+    print!("{}", script);
+}
+
+fn option_flags(option: &HelpOption) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(short) = &option.short {
+        flags.push(format!("-{}", short));
+    }
+    flags.push(format!("--{}", option.long.as_ref().unwrap()));
+    return flags;
+}
+
+fn takes_files(option: &HelpOption) -> bool {
+    return matches!(option.datatype.as_deref(), Some("path") | Some("dir"));
+}
+
+fn bash_completions(commands: &[CommandHelp], groups: &[OptionGroup]) -> String {
+    let command_names: Vec<&str> = commands.iter().map(|command| command.name.as_deref().unwrap()).collect();
+    let mut script = String::from("# bash completion for mofetch\n_mofetch() {\n");
+    script += "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n";
+    script += "    local command=show words=\"\"\n";
+    script += &format!("    case \"${{COMP_WORDS[1]}}\" in\n        {}|help) [[ $COMP_CWORD -gt 1 ]] && command=\"${{COMP_WORDS[1]}}\";;\n    esac\n\n",
+        command_names.join("|"));
+
+    // values of the option before the cursor
+    let all_options: Vec<&HelpOption> = groups.iter().flat_map(|group| group.options.iter()).collect();
+    script += "    case \"$prev\" in\n";
+    for option in all_options.iter().filter(|option| option.datatype.is_some()) {
+        let action = if takes_files(option) {
+            String::from("COMPREPLY=($(compgen -f -- \"$cur\"))")
+        } else if let Some(values) = &option.values {
+            format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", values.join(" "))
+        } else {
+            String::from("COMPREPLY=()")
+        };
+        script += &format!("        {}) {}; return;;\n", option_flags(option).join("|"), action);
+    }
+    script += "    esac\n\n";
+
+    script += "    case \"$command\" in\n";
+    for command in commands.iter() {
+        let options = command_options(groups, command);
+        let mut words: Vec<String> = options.iter().flat_map(|option| option_flags(option)).collect();
+        if !command.subcommands.is_empty() {
+            let subcommands: Vec<&str> = command.subcommands.iter().map(|sub| sub.name.as_deref().unwrap()).collect();
+            script += &format!("        {}) [[ $COMP_CWORD -eq 2 ]] && words=\"{}\" || words=\"{}\";;\n",
+                command.name.as_ref().unwrap(), subcommands.join(" "), words.join(" "));
+            continue;
+        }
+        if command.name.as_deref() == Some("completions") {
+            words.extend(["bash", "zsh", "fish"].map(String::from));
+        }
+        words.extend(["-h", "--help"].map(String::from));
+        script += &format!("        {}) words=\"{}\";;\n", command.name.as_ref().unwrap(), words.join(" "));
+    }
+    script += &format!("        help) [[ $COMP_CWORD -eq 2 ]] && words=\"{}\";;\n", command_names.join(" "));
+    script += "    esac\n";
+    script += &format!("    [[ $COMP_CWORD -eq 1 ]] && words=\"{} help $words\"\n\n", command_names.join(" "));
+
+    script += "    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n";
+    script += "    if [[ ($command == show || $command == render) && $cur != -* ]]; then\n";
+    script += "        COMPREPLY+=($(compgen -f -- \"$cur\"))\n";
+    script += "    fi\n}\ncomplete -o filenames -F _mofetch mofetch\n";
+    return script;
+}
+
+/// Escapes text for a single quoted zsh `_arguments` spec
+fn zsh_escape(text: &str) -> String {
+    return text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:");
+}
+
+fn zsh_completions(commands: &[CommandHelp], groups: &[OptionGroup]) -> String {
+    let mut script = String::from("#compdef mofetch\n\n_mofetch() {\n    local -a commands\n    commands=(\n");
+    for command in commands.iter() {
+        script += &format!("        '{}:{}'\n", command.name.as_ref().unwrap(), zsh_escape(command.desc.as_ref().unwrap()));
+    }
+    script += "    )\n\n";
+
+    for command in commands.iter().filter(|command| !command.groups.is_empty()) {
+        script += &format!("    local -a {}_options\n    {}_options=(\n", command.name.as_ref().unwrap(), command.name.as_ref().unwrap());
+        for option in command_options(groups, command) {
+            let flags = option_flags(option);
+            let mut spec = if flags.len() > 1 {
+                format!("'({})'{{{}}}'[{}]", flags.join(" "), flags.join(","), zsh_escape(option.desc.as_ref().unwrap()))
+            } else {
+                format!("'{}[{}]", flags[0], zsh_escape(option.desc.as_ref().unwrap()))
+            };
+            if let Some(datatype) = &option.datatype {
+                let action = if takes_files(option) {
+                    String::from("_files")
+                } else if let Some(values) = &option.values {
+                    format!("({})", values.join(" "))
+                } else {
+                    String::new()
+                };
+                spec += &format!(":{}:{}", datatype, action);
+            }
+            script += &format!("        {}'\n", spec);
+        }
+        script += "    )\n\n";
+    }
+
+    script += "    if (( CURRENT == 2 )) && [[ $PREFIX != -* ]]; then\n";
+    script += "        _describe 'command' commands\n        _files\n        return\n    fi\n\n";
+    script += "    case $words[2] in\n";
+    for command in commands.iter() {
+        let name = command.name.as_ref().unwrap();
+        if !command.subcommands.is_empty() {
+            let subcommands: Vec<String> = command.subcommands.iter()
+                .map(|sub| format!("'{}[{}]'", sub.name.as_ref().unwrap(), zsh_escape(sub.desc.as_ref().unwrap())))
+                .collect();
+            let options = if command.groups.is_empty() {String::new()} else {format!(" || _arguments -s ${}_options '*: :'", name)};
+            script += &format!("        {})\n            (( CURRENT == 3 )) && _values '{} command' {}{}\n            ;;\n",
+                name, name, subcommands.join(" "), options);
+        }
+        else if !command.groups.is_empty() {
+            script += &format!("        {})\n            shift words; (( CURRENT-- ))\n            _arguments -s ${}_options '*:input:_files'\n            ;;\n",
+                name, name);
+        }
+        else if name == "completions" {
+            script += "        completions)\n            (( CURRENT == 3 )) && _values 'shell' bash zsh fish\n            ;;\n";
+        }
+    }
+    script += "        help)\n            (( CURRENT == 3 )) && _describe 'command' commands\n            ;;\n";
+    script += "        *)\n            _arguments -s $show_options '*:input:_files'\n            ;;\n";
+    script += "    esac\n}\n\n_mofetch \"$@\"\n";
+    return script;
+}
+
+/// Escapes text for a single quoted fish string
+fn fish_escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('\'', "\\'");
+}
+
+fn fish_completions(commands: &[CommandHelp], groups: &[OptionGroup]) -> String {
+    let command_names: Vec<&str> = commands.iter().map(|command| command.name.as_deref().unwrap()).collect();
+    let mut script = String::from("# fish completion for mofetch\ncomplete -c mofetch -f\n");
+    for command in commands.iter() {
+        script += &format!("complete -c mofetch -n __fish_use_subcommand -a {} -d '{}'\n",
+            command.name.as_ref().unwrap(), fish_escape(command.desc.as_ref().unwrap()));
+    }
+
+    for command in commands.iter() {
+        let name = command.name.as_ref().unwrap();
+        // options given without a command belong to `show`
+        let condition = if name == "show" {
+            String::from("'__fish_use_subcommand; or __fish_seen_subcommand_from show'")
+        } else {
+            format!("'__fish_seen_subcommand_from {}'", name)
+        };
+        for subcommand in command.subcommands.iter() {
+            let subcommand_names: Vec<&str> = command.subcommands.iter().map(|sub| sub.name.as_deref().unwrap()).collect();
+            script += &format!("complete -c mofetch -n '__fish_seen_subcommand_from {}; and not __fish_seen_subcommand_from {}' -a {} -d '{}'\n",
+                name, subcommand_names.join(" "), subcommand.name.as_ref().unwrap(), fish_escape(subcommand.desc.as_ref().unwrap()));
+        }
+        if name == "completions" {
+            script += "complete -c mofetch -n '__fish_seen_subcommand_from completions' -a 'bash zsh fish'\n";
+        }
+        if command.groups.is_empty() {
+            continue;
+        }
+        if command.args.as_ref().is_some_and(|args| args.contains("input")) {
+            script += &format!("complete -c mofetch -n {} -F\n", condition);
+        }
+        for option in command_options(groups, command) {
+            let mut line = format!("complete -c mofetch -n {}", condition);
+            if let Some(short) = &option.short {
+                line += &format!(" -s {}", short);
+            }
+            line += &format!(" -l {}", option.long.as_ref().unwrap());
+            if takes_files(option) {
+                line += " -r -F";
+            } else if let Some(values) = &option.values {
+                line += &format!(" -x -a '{}'", values.join(" "));
+            } else if option.datatype.is_some() {
+                line += " -x";
+            }
+            line += &format!(" -d '{}'\n", fish_escape(option.desc.as_ref().unwrap()));
+            script += &line;
+        }
+    }
+    script += &format!("complete -c mofetch -n '__fish_seen_subcommand_from help' -a '{}'\n", command_names.join(" "));
+    return script;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_option_is_applied() {
+        let groups = help_options::init_options();
+        let commands = help_options::init_commands();
+        for name in ["show", "render"] {
+            let command = find_command(&commands, name).unwrap();
+            for option in command_options(&groups, command) {
+                let long = option.long.as_deref().unwrap();
+                // --help and --version exit, and the path options are taken out of the 
+                // arguments before the others are parsed
+                if ["help", "version", "config", "cache-dir", "data-dir"].contains(&long) {
+                    continue;
+                }
+                let value = option.values.as_ref().and_then(|values| values.first()).map(String::as_str).unwrap_or("1");
+                let mut parser = lexopt::Parser::from_args([value]);
+                let result = apply_option(&mut OptionsDefaults::default(), long, &mut parser);
+                assert!(result.is_ok(), "mofetch {} --{} {}: {:?}", name, long, value, result.err());
+            }
+        }
+    }
+}
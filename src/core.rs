@@ -2,6 +2,9 @@
 //! post-processing and then to frame buffer caching. It also includes the function to render 
 //! frame buffers to a terminal.
 
+#[path ="./shaders/preprocess_shader.rs"]
+mod preprocess_shader;
+
 #[path ="./shaders/dog_shader.rs"]
 mod dog_shader;
 
@@ -14,20 +17,213 @@ mod downscale_shader;
 #[path = "./utils.rs"]
 pub mod utils;
 
+pub use dog_shader::MAX_KERNEL_RADIUS;
+
 use std::process::Command;
 use std::fs;
+use std::path::{Path, PathBuf};
 use wgpu::{ComputePipeline, RenderPipeline};
-use std::fs::{File, read_to_string};
 use std::io::prelude::*;
-use image::GenericImageView;
 use downscale_shader::WorkgroupSize;
 use std::time::{Instant,Duration};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::collections::{BTreeMap, VecDeque};
 
 use crate::TERM_FONT_DIMS;
+use crate::frame_cache;
+use crate::progress;
 
 pub struct FfmpegConfig <'a> {
     pub input_path: &'a str,
-    pub fps: &'a u16,
+    pub fps: &'a f64,
+    pub frames_path: &'a Path,
+    /// trim the source, in any time format ffmpeg accepts (e.g. "12.5" or "1:30")
+    pub start: Option<&'a str>,
+    pub end: Option<&'a str>,
+    pub geometry: FrameGeometry,
+}
+
+/// How the source is fitted into the thumbnail box
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fit {
+    /// scale to fit inside the box, keeping the aspect ratio
+    Contain,
+    /// scale to fill the box, keeping the aspect ratio and cutting off the overflow
+    Cover,
+    /// scale to the box, ignoring the aspect ratio
+    Stretch,
+}
+
+impl std::str::FromStr for Fit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(format!("unknown fit mode \"{}\" (expected contain, cover or stretch)", s)),
+        };
+    }
+}
+
+/// Region of the source that is kept, before it is fitted
+#[derive(Clone, Copy, PartialEq)]
+pub enum Crop {
+    /// x, y, width and height in source pixels
+    Rect(u16, u16, u16, u16),
+    /// the largest centered square
+    CenterSquare,
+}
+
+impl std::str::FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "center-square" {
+            return Ok(Crop::CenterSquare);
+        }
+
+        let values: Result<Vec<u16>, _> = s.split(':').map(|v| v.trim().parse::<u16>()).collect();
+        return match values.as_deref() {
+            Ok([x, y, w, h]) if *w > 0 && *h > 0 => Ok(Crop::Rect(*x, *y, *w, *h)),
+            _ => Err(format!("invalid crop \"{}\" (expected x:y:w:h or center-square)", s)),
+        };
+    }
+}
+
+/// User settings for the size and framing of the thumbnail
+#[derive(Clone, Copy)]
+pub struct FrameGeometry {
+    pub fit: Fit,
+    pub crop: Option<Crop>,
+    /// exact thumbnail size in characters. If only one is set, the other follows the aspect ratio
+    pub target_cols: Option<u16>,
+    pub target_rows: Option<u16>,
+}
+
+/// The crop, scale and tile resolution of the frames, worked out together so the 
+/// thumbnail ends up with the expected size in characters.
+pub struct FramePlan {
+    /// x, y, width, height of the source region that is kept
+    pub crop: Option<(u16, u16, u16, u16)>,
+    /// size the kept region is scaled to
+    pub scale: (u16, u16),
+    /// centered region of the scaled frame that is kept, for `Fit::Cover`
+    pub fill: Option<(u16, u16)>,
+    pub frame_dims: (u16, u16),
+    pub tile_res: WorkgroupSize,
+}
+
+impl FramePlan {
+    /// The ffmpeg filter chain for this plan, if the source needs any changes
+    fn filter(&self, source_dims: (u16, u16)) -> Option<String> {
+        let mut filters = Vec::new();
+        let mut dims = source_dims;
+        if let Some((x, y, w, h)) = self.crop {
+            filters.push(format!("crop={}:{}:{}:{}", w, h, x, y));
+            dims = (w, h);
+        }
+        if self.scale != dims {
+            filters.push(format!("scale={}:{}", self.scale.0, self.scale.1));
+        }
+        if let Some((w, h)) = self.fill {
+            filters.push(format!("crop={}:{}", w, h));
+        }
+
+        if filters.is_empty() {
+            return None;
+        }
+        return Some(filters.join(","));
+    }
+}
+
+/// Works out how the source is cropped and scaled, and which tile resolution is used.
+/// `max_dims` is the thumbnail box in terminal pixels, which is used when no target 
+/// size in characters is set.
+pub fn plan_frames(source_dims: (u16, u16), max_dims: (u16, u16), geometry: &FrameGeometry) -> FramePlan {
+    let (source_width, source_height) = source_dims;
+
+    let crop = geometry.crop.map(|crop| match crop {
+        Crop::Rect(x, y, w, h) => {
+            let x = std::cmp::min(x, source_width - 1);
+            let y = std::cmp::min(y, source_height - 1);
+            (x, y, std::cmp::min(w, source_width - x), std::cmp::min(h, source_height - y))
+        }
+        Crop::CenterSquare => {
+            let side = std::cmp::min(source_width, source_height);
+            ((source_width - side) / 2, (source_height - side) / 2, side, side)
+        }
+    });
+    let (width, height) = match crop {
+        Some((_, _, w, h)) => (w, h),
+        None => source_dims,
+    };
+
+    // characters are TERM_FONT_DIMS pixels, so a square source is about twice as wide in columns as in rows
+    let char_aspect = TERM_FONT_DIMS.0 as f32 / TERM_FONT_DIMS.1 as f32;
+    let source_rows_per_col = height as f32 / width as f32 * char_aspect;
+    let targeted = geometry.target_cols.is_some() || geometry.target_rows.is_some();
+
+    let (cols, rows, tile_res) = if targeted {
+        let cols = geometry.target_cols.unwrap_or_else(|| {
+            std::cmp::max((geometry.target_rows.unwrap() as f32 / source_rows_per_col).round() as u16, 1)
+        });
+        let rows = geometry.target_rows.unwrap_or_else(|| {
+            std::cmp::max((cols as f32 * source_rows_per_col).round() as u16, 1)
+        });
+
+        // the largest tiles that don't need the source to be upscaled
+        let mut tile_res = SMALL_TILE;
+        for res in TILE_RESOLUTIONS.iter() {
+            if cols as u32 * res.x <= width as u32 && rows as u32 * res.y <= height as u32 {
+                tile_res = *res;
+            }
+        }
+        (cols, rows, tile_res)
+    }
+    else {
+        let tile_res = get_tile_res(width, height, max_dims.0, max_dims.1);
+        (max_dims.0 / TERM_FONT_DIMS.0, max_dims.1 / TERM_FONT_DIMS.1, tile_res)
+    };
+
+    // thumbnail box in frame pixels
+    let box_width = (cols as u32 * tile_res.x) as f32;
+    let box_height = (rows as u32 * tile_res.y) as f32;
+    let scale_x = box_width / width as f32;
+    let scale_y = box_height / height as f32;
+
+    let (scale, fill) = match geometry.fit {
+        Fit::Contain => {
+            // a single target size decides the scale on its own, so it is met exactly. 
+            // Without a target size, sources are only ever scaled down
+            let factor = match (geometry.target_cols, geometry.target_rows) {
+                (Some(_), None) => scale_x,
+                (None, Some(_)) => scale_y,
+                (Some(_), Some(_)) => f32::min(scale_x, scale_y),
+                (None, None) => f32::min(f32::min(scale_x, scale_y), 1.0),
+            };
+            let scale = ((width as f32 * factor).round() as u16, (height as f32 * factor).round() as u16);
+            (scale, None)
+        }
+        Fit::Cover => {
+            let factor = f32::max(scale_x, scale_y);
+            let scale = ((width as f32 * factor).ceil() as u16, (height as f32 * factor).ceil() as u16);
+            (scale, Some((box_width as u16, box_height as u16)))
+        }
+        Fit::Stretch => ((box_width as u16, box_height as u16), None),
+    };
+    let scale = (std::cmp::max(scale.0, 1), std::cmp::max(scale.1, 1));
+
+    return FramePlan {
+        crop,
+        scale,
+        fill,
+        frame_dims: fill.unwrap_or(scale),
+        tile_res,
+    };
 }
 
 // Resolutions for ascii tiles;
@@ -60,22 +256,161 @@ const SMALL_TILE: downscale_shader::WorkgroupSize = downscale_shader::WorkgroupS
 const TILE_RESOLUTIONS: [WorkgroupSize;4] = [SMALL_TILE,MEDIUM_TILE,LARGE_TILE,LARGEST_TILE];
 
 pub struct FFmpegReturn {
-    pub frame_count: i32,
+    pub frames: FrameFiles,
+    /// dimensions of the frames after cropping and scaling
     pub width: u16,
     pub height: u16,
+    pub tile_res: WorkgroupSize,
+}
+
+/// The png frames of a source. ffmpeg extracts them in the background, so the first 
+/// frames can be processed while the rest are still being extracted.
+#[derive(Clone)]
+pub struct FrameFiles {
+    pub path: PathBuf,
+    // the number of frames, set once ffmpeg is done
+    count: Arc<OnceLock<usize>>,
+    // the frames extracted so far, as reported by ffmpeg
+    decoded: Arc<AtomicUsize>,
+    // the number of frames estimated from ffprobe, for the progress line
+    expected: Option<usize>,
 }
 
-fn get_frames_path() -> String {
-    format!("{}/mofetch/frames",dirs::data_dir().unwrap().to_str().unwrap())
+impl FrameFiles {
+    fn frame_path(&self, index: usize) -> PathBuf {
+        return self.path.join(format!("output_frame_{}.png", index + 1));
+    }
+
+    /// The number of frames, once ffmpeg is done
+    pub fn count(&self) -> Option<usize> {
+        return self.count.get().copied();
+    }
+
+    /// The number of frames extracted so far
+    pub fn decoded(&self) -> usize {
+        return self.count().unwrap_or(self.decoded.load(Ordering::Relaxed));
+    }
+
+    /// The number of frames once ffmpeg is done, or the ffprobe estimate before that
+    pub fn total(&self) -> Option<usize> {
+        return self.count().or(self.expected);
+    }
+
+    /// Waits until frame `index` is extracted. Returns false if the source has fewer frames.
+    pub fn wait_for(&self, index: usize) -> bool {
+        loop {
+            if let Some(count) = self.count() {
+                return index < count;
+            }
+            // ffmpeg writes the frames in order, so a frame is complete once the next one is started
+            if fs::exists(self.frame_path(index + 1)).unwrap_or(false) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
 }
 
-/// Runs the ffmpeg command to break down the input from a path into png frames, 
-/// storing them in a `frames` directory.
+// file extensions of the media a directory is searched for
+const MEDIA_EXTENSIONS: [&str; 14] = ["gif", "png", "apng", "jpg", "jpeg", "webp", "bmp", "tiff", "mp4", "m4v", "webm", "mkv", "mov", "avi"];
+
+/// The media files directly inside `dir`, sorted by path
+pub fn media_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| MEDIA_EXTENSIONS.contains(&ext.to_lowercase().as_str())))
+        .collect();
+    files.sort();
+    return Ok(files);
+}
+
+/// Picks one of the media files in `dir` at random
+pub fn random_media_file(dir: &Path) -> Result<String, String> {
+    use std::hash::{BuildHasher, Hasher};
+
+    let files = media_files(dir).map_err(|error| format!("could not read {}: {}", dir.display(), error))?;
+    if files.is_empty() {
+        return Err(format!("there are no media files in {}", dir.display()));
+    }
+    // the hasher of every process is randomly seeded, which is random enough for a thumbnail
+    let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    let file = &files[(random % files.len() as u64) as usize];
+    return file.to_str().map(String::from).ok_or(format!("{} is not a valid UTF-8 path", file.display()));
+}
+
+/// ffmpeg's native VP8/VP9 decoders drop the alpha channel of WebM files, so sources with 
+/// alpha have to be decoded with libvpx instead. Returns the decoder to use, if any.
+fn alpha_decoder(input_path: &str) -> Option<&'static str> {
+    let probe_stdout = Command::new("ffprobe")
+        .args(["-hide_banner", "-loglevel", "fatal", "-select_streams", "v:0", "-show_entries",
+            "stream=codec_name:stream_tags=alpha_mode", "-of", "csv=p=0", input_path])
+        .output()
+        .ok()?;
+
+    let output = String::from_utf8_lossy(&probe_stdout.stdout);
+    let mut fields = output.trim().split(',');
+    let codec = fields.next()?;
+    if fields.next() != Some("1") {
+        return None;
+    }
+    return match codec {
+        "vp8" => Some("libvpx"),
+        "vp9" => Some("libvpx-vp9"),
+        _ => None,
+    };
+}
+
+/// Parses an ffmpeg time like `90`, `12.5s`, `1:30` or `1:02:30.5` into seconds. Only used 
+/// for the frame count estimate, so times it can't read are left to ffmpeg.
+fn parse_time(time: &str) -> Option<f64> {
+    let time = time.trim();
+    let (negative, time) = match time.strip_prefix('-') {
+        Some(time) => (true, time),
+        None => (false, time),
+    };
+    let mut seconds = 0.0;
+    for part in time.trim_end_matches('s').split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    return Some(if negative {-seconds} else {seconds});
+}
+
+/// Estimates the number of frames ffmpeg will extract from the duration of the source, 
+/// or its frame count when the duration isn't known, at the output fps.
+fn probe_frame_count(config: &FfmpegConfig) -> Option<usize> {
+    let probe_stdout = Command::new("ffprobe")
+        .args(["-hide_banner", "-loglevel", "fatal", "-select_streams", "v:0", "-show_entries",
+            "stream=nb_frames,r_frame_rate,duration:format=duration", "-of", "default=noprint_wrappers=1", config.input_path])
+        .output()
+        .ok()?;
+
+    let output = String::from_utf8_lossy(&probe_stdout.stdout);
+    let field = |name: &str| output.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix('=')?.parse::<f64>().ok());
+    let frame_rate = output.lines()
+        .find_map(|line| line.strip_prefix("r_frame_rate="))
+        .and_then(|rate| rate.split_once('/'))
+        .and_then(|(numerator, denominator)| Some(numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?))
+        .filter(|rate| rate.is_finite() && *rate > 0.0);
+    let duration = field("duration")
+        .or_else(|| Some(field("nb_frames")? / frame_rate?))?;
+
+    let start = config.start.and_then(parse_time).unwrap_or(0.0).clamp(0.0, duration);
+    let end = config.end.and_then(parse_time).unwrap_or(duration).clamp(start, duration);
+    return Some(std::cmp::max(((end - start) * config.fps).round() as usize, 1));
+}
+
+/// Starts the ffmpeg command to break down the input from a path into png frames, 
+/// storing them in a `frames` directory. Returns while ffmpeg is still running; 
+/// `FrameFiles` tells when the frames are there.
 pub fn get_frames(config: &FfmpegConfig, max_width: u16, max_height: u16, verbose: bool) -> FFmpegReturn {
     // make sure output dir is cleared
-    let frames_path = get_frames_path();
-    fs::remove_dir_all(&frames_path).ok();
-    fs::create_dir_all(&frames_path).unwrap();
+    let frames_path = config.frames_path;
+    fs::remove_dir_all(frames_path).ok();
+    fs::create_dir_all(frames_path).unwrap();
 
     // get media dimensions, used for resizing the source and choosing a tile resolution later
     let dims_stdout = Command::new("ffprobe")
@@ -95,54 +430,68 @@ pub fn get_frames(config: &FfmpegConfig, max_width: u16, max_height: u16, verbos
     let fps_string = config.fps.to_string();
     let mut ffmpeg_process = Command::new("ffmpeg");
     let ffmpeg_log_level = if verbose {"info"} else {"fatal"};
-    ffmpeg_process.args(["-hide_banner", "-loglevel",ffmpeg_log_level, "-i", config.input_path,"-r", fps_string.as_str()]);
+    ffmpeg_process.args(["-hide_banner", "-loglevel",ffmpeg_log_level]);
+    if let Some(start) = config.start {
+        ffmpeg_process.args(["-ss", start]);
+    }
+    if let Some(end) = config.end {
+        ffmpeg_process.args(["-to", end]);
+    }
+    if let Some(decoder) = alpha_decoder(config.input_path) {
+        ffmpeg_process.args(["-c:v", decoder]);
+    }
+    ffmpeg_process.args(["-i", config.input_path,"-r", fps_string.as_str()]);
+    ffmpeg_process.stdin(std::process::Stdio::null()); // ffmpeg otherwise reads keystrokes from the terminal
     
-    // compress source and retain aspect ratio if width or height exceed the max (from user args -W and -H)
-    if width > max_width || height > max_height {
-        let preferred_aspect_ratio = max_width as f32 / max_height as f32;
-        let source_aspect_ratio = width as f32 / height as f32;
-
-        let adjustment;
-        let scaled_resolution: (u16,u16) =
-            if preferred_aspect_ratio > source_aspect_ratio {
-                adjustment = String::from("width");
-                ((width as f32 * (max_height as f32 / height as f32)) as u16, max_height)
-            }
-            else {
-                adjustment = String::from("height");
-                (max_width, (height as f32 * (max_width as f32 / width as f32)) as u16)
-        };
-        let scaled_res_string = format!("scale={}:{}",scaled_resolution.0, scaled_resolution.1);
-        let compression_args = ("-vf",&scaled_res_string);
-        ffmpeg_process.args([compression_args.0,compression_args.1]);
+    // crop, then scale the source into the thumbnail box (from user args -W and -H, or the target size)
+    let plan = plan_frames((width, height), (max_width, max_height), &config.geometry);
+    if let Some(filter) = plan.filter((width, height)) {
+        ffmpeg_process.args(["-vf", filter.as_str()]);
+    }
 
-        if verbose {
-            println!("source_width: {}, source_height: {} | max_width: {}, max_height: {}",width,height, max_width,max_height);
-            if !adjustment.is_empty() {
-                println!("Adjusted {} | width: {}, height: {}",adjustment,scaled_resolution.0,scaled_resolution.1);
-            }
-        }
+    if verbose {
+        println!("source_width: {}, source_height: {} | max_width: {}, max_height: {}",width,height, max_width,max_height);
+        println!("Frames: {}x{} | tiles: ({},{}) | filter: {}", plan.frame_dims.0, plan.frame_dims.1,
+            plan.tile_res.x, plan.tile_res.y, plan.filter((width, height)).unwrap_or(String::from("none")));
     }
 
     if verbose {println!("");}
 
-    println!("Processing source...");
-    ffmpeg_process.arg(format!("{}/output_frame_%d.png", frames_path));
-    ffmpeg_process.status().unwrap();
-
-    if verbose {println!("");}
+    // ffmpeg reports its progress as key=value lines on stdout
+    ffmpeg_process.args(["-progress", "pipe:1"]);
+    ffmpeg_process.stdout(std::process::Stdio::piped());
+    ffmpeg_process.arg(frames_path.join("output_frame_%d.png"));
+    let mut ffmpeg_child = ffmpeg_process.spawn().unwrap();
+
+    let frames = FrameFiles {
+        path: PathBuf::from(frames_path),
+        count: Arc::new(OnceLock::new()),
+        decoded: Arc::new(AtomicUsize::new(0)),
+        expected: probe_frame_count(config),
+    };
+    let count = frames.count.clone();
+    let decoded = frames.decoded.clone();
+    let frames_path = PathBuf::from(frames_path);
+    std::thread::spawn(move || {
+        if let Some(stdout) = ffmpeg_child.stdout.take() {
+            for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Some(frame) = line.strip_prefix("frame=").and_then(|frame| frame.trim().parse().ok()) {
+                    decoded.store(frame, Ordering::Relaxed);
+                }
+            }
+        }
+        ffmpeg_child.wait().ok();
 
-    // count amount of frames to determine if output is a video or image and should be looped or not
-    let paths = fs::read_dir(&frames_path).unwrap();
-    let mut total_frames = 0;
-    for _ in paths {
-        total_frames += 1;
-    }
+        // count amount of frames to determine if output is a video or image and should be looped or not
+        let total_frames = fs::read_dir(frames_path).map(|paths| paths.count()).unwrap_or(0);
+        count.set(total_frames).ok();
+    });
 
     return FFmpegReturn {
-        frame_count: total_frames,
-        width,
-        height
+        frames,
+        width: plan.frame_dims.0,
+        height: plan.frame_dims.1,
+        tile_res: plan.tile_res,
     };
 }
 
@@ -220,18 +569,86 @@ impl Benchmark {
     }
 }
 
+// Amount of frames that are rendered on the GPU at the same time. Each frame in flight 
+// has its own set of textures and buffers.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+struct DecodedFrame {
+    index: usize,
+    rgba: image::RgbaImage,
+    decode_time: Duration,
+}
+
+/// Decodes the png frames on a pool of threads as ffmpeg extracts them. Frames are sent 
+/// in roughly ascending order, but can arrive out of order. A frame that can't be decoded 
+/// is sent as an error, and its thread stops.
+fn spawn_decoders(frames: &FrameFiles) -> Receiver<Result<DecodedFrame, String>> {
+    let thread_count = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let thread_count = std::cmp::max(1, std::cmp::min(thread_count, frames.count().unwrap_or(usize::MAX)));
+
+    // bounded, so decoding does not run too far ahead of the GPU
+    let (tx, rx) = std::sync::mpsc::sync_channel(FRAMES_IN_FLIGHT * 2);
+    let next_frame = Arc::new(AtomicUsize::new(0));
+    for _ in 0..thread_count {
+        let tx = tx.clone();
+        let next_frame = next_frame.clone();
+        let frames = frames.clone();
+        std::thread::spawn(move || {
+            loop {
+                let index = next_frame.fetch_add(1, Ordering::Relaxed);
+                if !frames.wait_for(index) {
+                    return;
+                }
+
+                let benchmark_image_decode = Instant::now();
+                let frame_path = frames.frame_path(index);
+                let frame = image::ImageReader::open(&frame_path)
+                    .map_err(image::ImageError::IoError)
+                    .and_then(|reader| reader.decode())
+                    .map(|image| DecodedFrame {
+                        index,
+                        rgba: image.to_rgba8(),
+                        decode_time: benchmark_image_decode.elapsed(),
+                    })
+                    .map_err(|error| format!("could not decode frame {}: {}", frame_path.display(), error));
+                let failed = frame.is_err();
+                if tx.send(frame).is_err() || failed {
+                    return;
+                }
+            }
+        });
+    }
+    return rx;
+}
+
 /// Processes each frame with the shader process and caches the resulting ASCII 
 /// text buffers.
-pub fn process_frames(frame_count: &i32, process_desc: &ProcessDescriptor,
-    cache_file: File, width: u16, height: u16, max_width: u16, max_height: u16, shader_config: utils::ShaderConfig,
-    verbose: bool,
-) {
-    println!("Processing frames...");
-
+/// 
+/// The processing is pipelined: frames are decoded on a thread pool while up to 
+/// `FRAMES_IN_FLIGHT` frames are rendered on the GPU, and results are written in order.
+/// With `show_progress`, a progress line is drawn on stderr when it is a terminal.
+/// 
+/// Fails when a frame can't be decoded. The cache is left unfinished then, as it is 
+/// missing frames.
+pub fn process_frames(source: &FFmpegReturn, process_desc: &ProcessDescriptor,
+    cache: &mut frame_cache::CacheWriter, shader_config: utils::ShaderConfig,
+    dump_stages: Option<&DumpStages>, verbose: bool, show_progress: bool,
+) -> Result<(), String> {
     let device = &process_desc.device;
 
     // Init shader pipelines
-    // DoG shader
+    // pre-process shader; image adjustments that feed both the edge and the luminance passes
+    let preprocess_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader_code/preprocess_shader.wgsl").into()),
+    });
+
+    // DoG shaders; a horizontal blur pass, then a vertical blur pass that subtracts the blurs
+    let dog_blur_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader_code/dog_blur_shader.wgsl").into()),
+    });
+
     let dog_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: None,
         source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader_code/dog_shader.wgsl").into()),
@@ -246,7 +663,7 @@ pub fn process_frames(frame_count: &i32, process_desc: &ProcessDescriptor,
     // downscale compute shader; Each alternative is the same except with different workgroup sizes, 
     // since those cannot be runtime variables. Each workgroup size determines the source resolution 
     // for each ascii tile 
-    let target_res = get_tile_res(width, height, max_width, max_height);
+    let target_res = source.tile_res;
     let shader_source =
         if target_res.x == SMALL_TILE.x {
             wgpu::ShaderSource::Wgsl(include_str!("shaders/shader_code/downscale_shader_small.wgsl").into())
@@ -265,321 +682,844 @@ pub fn process_frames(frame_count: &i32, process_desc: &ProcessDescriptor,
             source: shader_source,
     });
     
+    let preprocess_desc = utils::create_render_pipeline_desc(&preprocess_module);
+    let mut dog_blur_desc = utils::create_render_pipeline_desc(&dog_blur_module);
+    dog_blur_desc.fragment.as_mut().unwrap().targets = &dog_shader::BLUR_TARGETS;
     let dog_desc = utils::create_render_pipeline_desc(&dog_module);
     let sobel_desc = utils::create_render_pipeline_desc(&sobel_module);
 
-    let dog_pipeline = device.create_render_pipeline(&dog_desc);
-    let sobel_pipeline = device.create_render_pipeline(&sobel_desc);
-    let ds_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: None,
-        module: &ds_module,
-        layout: None,
-        entry_point: Some("main"),
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-        cache: None,
-    });
+    let pipelines = Pipelines {
+        preprocess: device.create_render_pipeline(&preprocess_desc),
+        dog_blur: device.create_render_pipeline(&dog_blur_desc),
+        dog: device.create_render_pipeline(&dog_desc),
+        sobel: device.create_render_pipeline(&sobel_desc),
+        ds: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            module: &ds_module,
+            layout: None,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        }),
+    };
 
     // start image processing
-    let is_image = if *frame_count == 1 {1} else {0};
     let mut benchmark = Benchmark::init();
+    let benchmark_total = Instant::now();
 
     if verbose { println!("Using GPU adapter: {:?}", process_desc.adapter.get_info().name); }
+    if verbose { println!("Processing frames of {}x{}", source.width, source.height); }
 
-    let frames_path = get_frames_path();
-    for n in 1..frame_count + is_image {
-        let frame_path = format!("{}/output_frame_{}.png",frames_path, n);
-        let new_benchmark = pollster::block_on(shader_process(
-            frame_path.as_str(), &process_desc, &cache_file,&dog_pipeline,&sobel_pipeline,&ds_pipeline,
-            target_res, &shader_config, verbose,
-        ));
-        
-        benchmark.total_time += new_benchmark.total_time;
-        benchmark.image_decode_time += new_benchmark.image_decode_time;
-        benchmark.render_time += new_benchmark.render_time;
-        benchmark.cache_time += new_benchmark.cache_time;
+    if let Some(dump_stages) = dump_stages {
+        fs::create_dir_all(&dump_stages.dir).expect("Error: Could not create the --dump-stages directory");
     }
 
+    let decoded_rx = spawn_decoders(&source.frames);
+    // the per-frame lines of verbose mode would be drawn over
+    let mut progress_bar = if show_progress && !verbose {progress::ProgressBar::new("Processing")} else {None};
+    let mut frames_done = 0;
+
+    // frames that were decoded ahead of the next frame in line
+    let mut decoded_ahead: BTreeMap<usize, DecodedFrame> = BTreeMap::new();
+    let mut slots: Vec<FrameSlot> = Vec::new();
+    let mut in_flight: VecDeque<usize> = VecDeque::new();
+    // glyphs of the last cached frame, for the hysteresis of the error diffusion
+    let mut last_levels: Vec<u32> = Vec::new();
+
+    // the decoders stop once every frame is decoded
+    let mut frame_total = 0;
+    loop {
+        let n = frame_total;
+        let frame = loop {
+            if let Some(frame) = decoded_ahead.remove(&n) {
+                break Some(frame);
+            }
+            match decoded_rx.recv() {
+                Ok(Ok(frame)) => decoded_ahead.insert(frame.index, frame),
+                Ok(Err(error)) => {
+                    // the buffers of the frames in flight are still being mapped, which has 
+                    // to finish before they are dropped
+                    device.poll(wgpu::PollType::Wait).ok();
+                    return Err(error);
+                }
+                Err(_) => break None,
+            };
+        };
+        let Some(frame) = frame else {
+            break;
+        };
+        frame_total += 1;
+        benchmark.image_decode_time += frame.decode_time;
+
+        // every frame has the same dimensions, so the slots are made once the first frame is decoded
+        if slots.is_empty() {
+            let texture_size = wgpu::Extent3d {
+                width: frame.rgba.width(),
+                height: frame.rgba.height(),
+                depth_or_array_layers: 1,
+            };
+
+            // the tiles of the last rendered frame, shared by all slots. Frames are submitted 
+            // in order, so each frame sees the history written by the one before it
+            let history_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("tile history buffer"),
+                size: 2 * ascii_buffer_size(texture_size, target_res),
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            for _ in 0..std::cmp::min(FRAMES_IN_FLIGHT, source.frames.count().unwrap_or(FRAMES_IN_FLIGHT)) {
+                slots.push(FrameSlot::new(
+                    process_desc, texture_size, &pipelines, &shader_config, target_res, dump_stages, &history_buffer,
+                ));
+            }
+        }
+
+        // the slot is reused in a round robin, so the oldest frame in flight has to be done first
+        let slot_index = n % slots.len();
+        if in_flight.len() == slots.len() {
+            let oldest = in_flight.pop_front().unwrap();
+            let frame_benchmark = slots[oldest].finish(process_desc, cache, &mut last_levels, verbose);
+            benchmark.render_time += frame_benchmark.render_time;
+            benchmark.cache_time += frame_benchmark.cache_time;
+            frames_done += 1;
+            if let Some(progress_bar) = progress_bar.as_mut() {
+                progress_bar.update(frames_done, source.frames.decoded(), source.frames.total());
+            }
+        }
+
+        slots[slot_index].submit(process_desc, &frame);
+        in_flight.push_back(slot_index);
+    }
+
+    while let Some(oldest) = in_flight.pop_front() {
+        let frame_benchmark = slots[oldest].finish(process_desc, cache, &mut last_levels, verbose);
+        benchmark.render_time += frame_benchmark.render_time;
+        benchmark.cache_time += frame_benchmark.cache_time;
+        frames_done += 1;
+        if let Some(progress_bar) = progress_bar.as_mut() {
+            progress_bar.update(frames_done, source.frames.decoded(), source.frames.total());
+        }
+    }
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+    benchmark.total_time = benchmark_total.elapsed();
+
     // get average benchmark times
     if verbose {
-        benchmark.average(*frame_count as u32);
-        println!("Total processing time: {:.3?} | AVERAGE: image_decode: {:.3?} | render: {:.3?} | cache: {:.3?}",
-            benchmark.total_time, benchmark.image_decode_time, benchmark.render_time, benchmark.cache_time);
+        benchmark.average(frame_total as u32);
+        let frames_per_second = frame_total as f64 / benchmark.total_time.as_secs_f64();
+        println!("Total processing time: {:.3?} ({:.1} frames/s) | AVERAGE: image_decode: {:.3?} | render: {:.3?} | cache: {:.3?}",
+            benchmark.total_time, frames_per_second, benchmark.image_decode_time, benchmark.render_time, benchmark.cache_time);
     }
 
-    fs::remove_dir_all(frames_path).ok();
+    fs::remove_dir_all(&source.frames.path).ok();
+    return Ok(());
+}
+
+/// Where the thumbnail and the info pane are placed in the terminal. Recomputed 
+/// every time the terminal is resized.
+#[derive(Clone, Copy, Default)]
+pub struct Layout {
+    pub frame_col: u16,
+    pub frame_row: u16,
+    pub clip_width: u16,
+    pub clip_height: u16,
+    pub info_col: u16,
+    pub info_row: u16,
 }
 
-struct CursorPos {
-    x: u16,
-    y: u16,
+// columns needed next to the thumbnail before the info pane is moved below it instead
+const INFO_MIN_WIDTH: u16 = 40;
+
+impl Layout {
+    pub fn new(frame_dims: (u32,u32), term_size: (u16,u16), hide_info: bool) -> Layout {
+        let frame_width = frame_dims.0 as u16;
+        let frame_height = frame_dims.1 as u16;
+        let (term_width, term_height) = term_size;
+
+        // the thumbnail is only centered when it is alone in the terminal
+        let frame_col =
+            if hide_info && term_width > frame_width {(term_width - frame_width) / 2 + 1}
+            else {1};
+        let frame_row = 1;
+
+        // clip the thumbnail if it does not fit. The last row is left free for the cursor
+        let clip_width = std::cmp::min(frame_width, term_width.saturating_sub(frame_col - 1));
+        let clip_height = std::cmp::min(frame_height, term_height.saturating_sub(1));
+
+        let (info_col, info_row) =
+            if frame_width + 2 + INFO_MIN_WIDTH <= term_width {(frame_width + 2, 2)}
+            else {(2, clip_height + 2)};
+
+        return Layout {
+            frame_col,
+            frame_row,
+            clip_width,
+            clip_height,
+            info_col,
+            info_row,
+        };
+    }
 }
-/// Renders and loops the ASCII txt frames through stdout.
-pub fn print_frame_loop(cache_path: &str, is_image: bool) {
-    let file_string = read_to_string(cache_path).unwrap();
 
-    // read fps configuration from cache file
-    let config_line = file_string.lines().nth(0).unwrap();
-    let config_string = &String::from(config_line)[3..];
-    let fps_iter: Vec<&str> = config_string.split('=').collect();
-    let fps = fps_iter[1].parse::<i32>().unwrap();
+/// Layout shared between the playback loop and the sysinfo thread. `generation` is 
+/// bumped every time the layout changes, which tells the sysinfo thread to redraw. 
+/// The sysinfo thread reports back through `drawn_generation` once it has.
+#[derive(Default)]
+pub struct SharedLayout {
+    pub layout: Mutex<Layout>,
+    pub generation: AtomicUsize,
+    pub drawn_generation: AtomicUsize,
+}
 
-    let frame_duration = (1000.0/(fps as f32)).ceil() as u64;
+/// User controls for how the cached frames are played back.
+pub struct PlaybackSettings {
+    /// stop after this many loops, leaving the last frame on screen
+    pub loops: Option<u32>,
+    /// stop after this many seconds
+    pub duration: Option<f32>,
+    pub speed: f32,
+    /// play forwards, then backwards
+    pub ping_pong: bool,
+    /// print the measured playback fps below the thumbnail
+    pub show_fps: bool,
+}
 
-    let mut cursor_pos = CursorPos {
-        x: 2,
-        y: 2,
-    };
-    let mut frame_buffer: String = Default::default();
-    print!("{}{}",termion::clear::All, termion::cursor::Goto(cursor_pos.x,cursor_pos.y));
+pub type ResizeCallback = Box<dyn FnMut((u16,u16))>;
+
+pub struct PlaybackContext {
+    pub shared_layout: Arc<SharedLayout>,
+    pub hide_info: bool,
+    /// The sysinfo thread, unless the info is hidden
+    pub info_thread: Option<std::thread::JoinHandle<()>>,
+    pub settings: PlaybackSettings,
+    /// Set by the SIGWINCH handler
+    pub resized: Arc<AtomicBool>,
+    /// Receives caches that were re-rendered for a new terminal size
+    pub variant_rx: Option<Receiver<PathBuf>>,
+    /// Called with the new terminal size (in chars) after each resize
+    pub on_resize: Option<ResizeCallback>,
+    /// The cache while it is still being rendered (`--progressive`). It is played instead 
+    /// of opening the cache path, and playback waits for frames that aren't rendered yet.
+    pub rendering: Option<frame_cache::CacheReader>,
+}
 
-    loop {
-        for line in file_string.lines() {
-            // if line is a frame separator
-            if line.len() <= 1 {
-                cursor_pos.x = 1;
-                cursor_pos.y = 1;
-
-                // if end of frame (if frame separator appears when buffer is not empty)
-                if frame_buffer.len() > 1 {
-                    print!("{}{}",termion::cursor::Goto(cursor_pos.x,cursor_pos.y),frame_buffer);
-                    frame_buffer.clear();
-                    std::thread::sleep(Duration::from_millis(frame_duration));
+impl PlaybackContext {
+    /// Clears the screen and publishes a new layout for the thumbnail and the info pane.
+    fn relayout(&self, frame_dims: (u32,u32), term_size: (u16,u16)) -> Layout {
+        print!("{}", termion::clear::All);
+        std::io::stdout().flush().ok();
+
+        let layout = Layout::new(frame_dims, term_size, self.hide_info);
+        *self.shared_layout.layout.lock().unwrap() = layout;
+        self.shared_layout.generation.fetch_add(1, Ordering::Relaxed);
+        return layout;
+    }
+
+    /// Ends playback with the last frame and the info pane left on screen, and moves the 
+    /// cursor below both so the shell prompt does not overwrite them.
+    fn finish(&self, layout: &Layout) {
+        let mut bottom_row = layout.frame_row + layout.clip_height;
+
+        if !self.hide_info {
+            // wait for the sysinfo thread to draw the final layout. A sysinfo thread that 
+            // died never does, and playback has to exit regardless
+            let generation = self.shared_layout.generation.load(Ordering::Relaxed);
+            while self.shared_layout.drawn_generation.load(Ordering::Relaxed) != generation
+                && self.info_thread.as_ref().is_some_and(|thread| !thread.is_finished()) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            bottom_row = std::cmp::max(bottom_row, layout.info_row + 8);
+        }
+        println!("{}", termion::cursor::Goto(1, bottom_row));
+    }
+}
+
+/// Order in which frames are played during one loop.
+fn frame_order(frame_count: usize, ping_pong: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..frame_count).collect();
+
+    // the first and last frames are not repeated when changing direction
+    if ping_pong && frame_count > 2 {
+        order.extend((1..frame_count - 1).rev());
+    }
+    return order;
+}
+
+// unchanged cells between two changed runs of a row are reprinted up to this length, 
+// since moving the cursor past them takes about as many bytes
+const REPRINT_GAP: usize = 8;
+
+/// Draws frames at the thumbnail position, clipped to the layout. Only the cells that changed 
+/// since the last drawn frame are printed, unless that takes more bytes than the frame itself.
+struct FrameRenderer {
+    // the visible cells of the frame on screen, row by row. Empty when the screen was cleared
+    shown: Vec<u8>,
+    visible: Vec<u8>,
+    output: String,
+}
+
+impl FrameRenderer {
+    fn new() -> FrameRenderer {
+        return FrameRenderer {
+            shown: Vec::new(),
+            visible: Vec::new(),
+            output: String::new(),
+        };
+    }
+
+    /// Makes the next frame draw in full, after the screen was cleared
+    fn invalidate(&mut self) {
+        self.shown.clear();
+    }
+
+    /// Draws a frame and returns the number of bytes written to the terminal
+    fn draw(&mut self, frame: &[u8], cols: u32, layout: &Layout) -> usize {
+        let width = std::cmp::min(cols as usize, layout.clip_width as usize);
+        self.visible.clear();
+        for line in frame.chunks(cols as usize).take(layout.clip_height as usize) {
+            self.visible.extend_from_slice(&line[..width]);
+        }
+
+        self.output.clear();
+        if self.shown.len() == self.visible.len() && width > 0 {
+            self.draw_changes(width, layout);
+        }
+        // a full redraw is also cheaper when most of the frame changed
+        if self.shown.len() != self.visible.len() || self.output.len() > self.visible.len() {
+            self.output.clear();
+            for (i, line) in self.visible.chunks(width.max(1)).enumerate() {
+                let line = std::str::from_utf8(line).unwrap_or_default();
+                self.output += format!("{}{}", termion::cursor::Goto(layout.frame_col, layout.frame_row + i as u16), line).as_str();
+            }
+        }
+
+        print!("{}", self.output);
+        std::io::stdout().flush().ok();
+        std::mem::swap(&mut self.shown, &mut self.visible);
+        return self.output.len();
+    }
+
+    /// Writes a cursor move and the cells for every run of changed cells
+    fn draw_changes(&mut self, width: usize, layout: &Layout) {
+        for (row, (line, shown_line)) in self.visible.chunks(width).zip(self.shown.chunks(width)).enumerate() {
+            let mut x = 0;
+            while x < width {
+                if line[x] == shown_line[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                let mut end = x + 1;
+                let mut next = end;
+                while next < width && next - end < REPRINT_GAP {
+                    if line[next] != shown_line[next] {
+                        end = next + 1;
+                    }
+                    next += 1;
                 }
+                let run = std::str::from_utf8(&line[start..end]).unwrap_or_default();
+                self.output += format!("{}{}",
+                    termion::cursor::Goto(layout.frame_col + start as u16, layout.frame_row + row as u16), run).as_str();
+                x = end;
             }
-            // else add frame line to frame_buffer
-            else if !line.contains("[]") {
-                frame_buffer += format!("{}\n",line).as_str();
+        }
+    }
+}
+
+/// Keeps track of the playback rate actually achieved, for verbose mode.
+struct FpsCounter {
+    window_start: Instant,
+    printed: u32,
+    dropped: u32,
+    bytes: usize,
+}
+
+impl FpsCounter {
+    fn new() -> FpsCounter {
+        return FpsCounter {
+            window_start: Instant::now(),
+            printed: 0,
+            dropped: 0,
+            bytes: 0,
+        };
+    }
+
+    /// Prints the measured fps and the bytes written per frame below the thumbnail about 
+    /// once per second.
+    fn report(&mut self, layout: &Layout) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        let fps = self.printed as f64 / elapsed.as_secs_f64();
+        let bytes_per_frame = self.bytes / std::cmp::max(self.printed, 1) as usize;
+        print!("{}fps: {:.2} | dropped: {} | {} B/frame   ",
+            termion::cursor::Goto(layout.frame_col, layout.frame_row + layout.clip_height), fps, self.dropped, bytes_per_frame);
+        std::io::stdout().flush().ok();
+        *self = FpsCounter::new();
+    }
+}
+
+/// Decodes a frame of the cache that is played
+fn read_frame(cache: &mut frame_cache::CacheReader, n: usize) -> &[u8] {
+    return cache.frame(n).expect("Error: Could not read cache");
+}
+
+/// Renders and loops the ASCII txt frames through stdout. The layout is recomputed when 
+/// the terminal is resized, and playback switches over to re-rendered caches as they arrive.
+/// 
+/// Frames are scheduled against a monotonic clock, so the time spent printing does not add 
+/// up over time. Frames are dropped when the terminal cannot keep up.
+pub fn print_frame_loop(cache_path: &Path, is_image: bool, mut playback: PlaybackContext) {
+    let mut cache_path = PathBuf::from(cache_path);
+    let mut cache = match playback.rendering.take() {
+        Some(cache) => cache,
+        None => frame_cache::CacheReader::open(&cache_path).expect("Error: Could not read cache"),
+    };
+    let mut info = cache.info();
+    let mut frame_dims = (info.cols, info.rows);
+    let mut term_size = termion::terminal_size().unwrap();
+    let mut layout = playback.relayout(frame_dims, term_size);
+    let mut renderer = FrameRenderer::new();
+
+    // the order of a cache that is still being rendered is extended as frames come in
+    let mut order = if cache.is_rendering() {Vec::new()} else {frame_order(cache.frame_count(), playback.settings.ping_pong)};
+    let playback_start = Instant::now();
+    let duration = playback.settings.duration.map(Duration::from_secs_f32);
+    let ends = playback.settings.loops.is_some() || duration.is_some();
+    let mut loops_played = 0;
+    let mut fps_counter = FpsCounter::new();
+    let mut next_deadline = Instant::now();
+
+    let mut n = 0;
+    loop {
+        if playback.resized.swap(false, Ordering::Relaxed) {
+            term_size = termion::terminal_size().unwrap();
+            layout = playback.relayout(frame_dims, term_size);
+            renderer.invalidate();
+            if let Some(on_resize) = playback.on_resize.as_mut() {
+                on_resize(term_size);
+            }
+            if is_image {
+                renderer.draw(read_frame(&mut cache, 0), info.cols, &layout);
             }
         }
+
+        // switch over to a cache that was rendered for the current terminal size
+        let variant = playback.variant_rx.as_ref().and_then(|rx| rx.try_recv().ok());
+        if let Some(variant_path) = variant && variant_path != cache_path {
+            cache_path = variant_path;
+            cache = frame_cache::CacheReader::open(&cache_path).expect("Error: Could not read cache");
+            info = cache.info();
+            frame_dims = (info.cols, info.rows);
+            order = frame_order(cache.frame_count(), playback.settings.ping_pong);
+            layout = playback.relayout(frame_dims, term_size);
+            renderer.invalidate();
+            n = 0;
+            if is_image {
+                renderer.draw(read_frame(&mut cache, 0), info.cols, &layout);
+            }
+        }
+
+        let time_is_up = duration.is_some_and(|duration| playback_start.elapsed() >= duration);
+
         if is_image {
-            // TODO: breaking this loop also kills the fetch thread. Maybe add an arbitrary loop here?
-            print!("{}{}",termion::cursor::Goto(cursor_pos.x,cursor_pos.y),frame_buffer);
+            if n == 0 {
+                renderer.draw(read_frame(&mut cache, 0), info.cols, &layout);
+                n = 1;
+            }
+
+            // without a duration, a static thumbnail is done once it is printed. Otherwise 
+            // keep updating the info pane
+            let image_done = duration.is_none() || time_is_up;
+            if image_done && (playback.hide_info || ends) {
+                playback.finish(&layout);
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+
+        if time_is_up {
+            playback.finish(&layout);
             break;
         }
+
+        // while the cache is still being rendered, the rendered frames are played in order 
+        // and playback stalls when it catches up with the render
+        if cache.is_rendering() {
+            cache.refresh().expect("Error: Could not render the thumbnail");
+            if cache.is_rendering() {
+                order.extend(order.len()..cache.frame_count());
+            }
+            else {
+                order = frame_order(cache.frame_count(), playback.settings.ping_pong);
+            }
+            if n >= order.len() {
+                std::thread::sleep(Duration::from_millis(10));
+                next_deadline = Instant::now();
+                continue;
+            }
+        }
+
+        fps_counter.bytes += renderer.draw(read_frame(&mut cache, order[n]), info.cols, &layout);
+        fps_counter.printed += 1;
+        if playback.settings.show_fps {
+            fps_counter.report(&layout);
+        }
+
+        let frame_interval = Duration::from_secs_f64(1.0 / info.fps / playback.settings.speed as f64);
+        next_deadline += frame_interval;
+
+        // skip the frames whose deadlines have already passed
+        let mut advance = 1;
+        let now = Instant::now();
+        if now > next_deadline {
+            let behind = (now - next_deadline).as_secs_f64() / frame_interval.as_secs_f64();
+            let dropped = behind.floor() as u32;
+            next_deadline += frame_interval * dropped;
+            fps_counter.dropped += dropped;
+            advance += dropped as usize;
+        }
+
+        n += advance;
+        if cache.is_rendering() {
+            // frames that aren't rendered yet are waited for, not skipped
+            n = std::cmp::min(n, order.len());
+        }
+        else if n >= order.len() {
+            loops_played += (n / order.len()) as u32;
+            n %= order.len();
+            if playback.settings.loops.is_some_and(|loops| loops_played >= loops) {
+                // the last frame may have been dropped
+                renderer.draw(read_frame(&mut cache, *order.last().unwrap()), info.cols, &layout);
+                playback.finish(&layout);
+                break;
+            }
+        }
+
+        let now = Instant::now();
+        if next_deadline > now {
+            std::thread::sleep(next_deadline - now);
+        }
     }
 }
 
 
-// TODO: need a way to get terminal font and calculate the brightness of each letter.
-// Will use this ascii string, which looks good on my terminal.
-const ASCII_STYLE: &str = " .,:?c79WNB@";
-// const ASCII_STYLE: &str = " .'`^,:;!i><~+-r?tfxjnuvczXQ0OZ#MW&8%B@$";
-// const ASCII_STYLE: &str = " .-+:;aAbBcCdDeEfFghHikKnNrR*?@";
-
 const ASCII_EDGES: &str = "|/_\\";
+// shape matched glyphs are stored as SHAPE_BASE + their index in SHAPE_GLYPHS
+const SHAPE_BASE: u32 = 10000;
+// tiles without coverage when the background is transparent. They are cached as blank cells
+const TRANSPARENT: u32 = 5000;
+
+struct Pipelines {
+    preprocess: RenderPipeline,
+    dog_blur: RenderPipeline,
+    dog: RenderPipeline,
+    sobel: RenderPipeline,
+    ds: ComputePipeline,
+}
 
-/// Processes an image with the ASCII shader algorithm and generates and caches a 
-/// text buffer to `cache_path` with the processed result.
-pub async fn shader_process(
-    frame_path: &str, desc: &ProcessDescriptor, file: &File, dog_pipeline: &RenderPipeline,
-    sobel_pipeline: &RenderPipeline, ds_pipeline: &ComputePipeline, wg_size: WorkgroupSize,
-    shader_config: &utils::ShaderConfig, verbose: bool,
-) -> Benchmark {
-
-    let device = &desc.device;
-    let queue = &desc.queue;
-
-    // create image
-    let benchmark_image_decode = Instant::now();
-    let diffuse_image = image::ImageReader::open(frame_path)
-        .unwrap().decode().unwrap();
-    let diffuse_rgba = diffuse_image.to_rgba8();
-    let image_decode_time = benchmark_image_decode.elapsed();
-
-    let benchmark_write_texture = Instant::now();
-    let dimensions = diffuse_image.dimensions();
-
-    let texture_size = wgpu::Extent3d {
-        width: dimensions.0,
-        height: dimensions.1,
-        depth_or_array_layers: 1,
-    };
-    let image_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("input texture"),
-        size: texture_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-
-    queue.write_texture(
-        wgpu::TexelCopyTextureInfo {
-            texture: &image_texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        },
-        &diffuse_rgba,
-        wgpu::TexelCopyBufferLayout {
-            offset: 0,
-            bytes_per_row: Some(texture_size.width * 4),
-            rows_per_image: Some(texture_size.height),
-        },
-        texture_size,
-    );
-    let write_texture_time = benchmark_write_texture.elapsed();
+/// GPU resources for one frame in flight. The textures, buffers and bind groups are made 
+/// once per resolution and reused for every frame rendered in this slot; only the input 
+/// texture contents change.
+struct FrameSlot<'a> {
+    image_texture: wgpu::Texture,
+    texture_size: wgpu::Extent3d,
+    ascii_buffer_size: wgpu::BufferAddress,
+    wg_size: WorkgroupSize,
+    preprocess_shader: preprocess_shader::PreprocessShader<'a>,
+    dog_shader: dog_shader::DogShader<'a>,
+    sobel_shader: sobel_shader::SobelShader<'a>,
+    ds_shader: downscale_shader::DownscaleShader<'a>,
+    shader_config: utils::ShaderConfig,
+    dump_stages: Option<&'a DumpStages>,
+    stage_buffers: Option<StageBuffers>,
+
+    // the frame currently in flight in this slot
+    submission: Option<wgpu::SubmissionIndex>,
+    submitted_at: Instant,
+    decode_time: Duration,
+    // frame number of the frame in flight, if its stages are dumped
+    dump_frame: Option<usize>,
+}
 
-    let benchmark_render = Instant::now();
+impl<'a> FrameSlot<'a> {
+    fn new(
+        desc: &ProcessDescriptor, texture_size: wgpu::Extent3d, pipelines: &'a Pipelines,
+        shader_config: &utils::ShaderConfig, wg_size: WorkgroupSize, dump_stages: Option<&'a DumpStages>,
+        history_buffer: &wgpu::Buffer,
+    ) -> FrameSlot<'a> {
+        let device = &desc.device;
+
+        let image_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("input texture"),
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
 
-    // Calculate buffer size for ascii storage/read buffer and round up to 
-    // nearest multiple of 256 to align buffer offset (prevents UnalignedCopyOffset 
-    // error).
-    let temp_buff_size = (4.0 * texture_size.width as f32 * texture_size.height as f32 / wg_size.x as f32 / wg_size.y as f32).floor();
-    let ascii_buffer_size = (256.0 * (temp_buff_size/256.0).ceil()) as wgpu::BufferAddress;
+        let ascii_buffer_size = ascii_buffer_size(texture_size, wg_size);
 
-    // create and compile shaders
-    let dog_shader = dog_shader::new(
-        &device, &image_texture, texture_size, dog_pipeline
-    );
-    let sobel_shader = sobel_shader::new(
-        &device, &dog_shader.render_target, texture_size, sobel_pipeline
-    );
-    let ds_shader = downscale_shader::new(downscale_shader::DownscaleShaderStruct {
-        device: &device,
-        texture: &image_texture,
-        sobel_texture: &sobel_shader.render_target,
-        size: texture_size,
-        buffer_size: &ascii_buffer_size,
-        ascii_style: ASCII_STYLE,
-
-        brightness: shader_config.brightness,
-        contrast: shader_config.contrast,
-        draw_edges: shader_config.draw_edges,
-        edge_threshold: shader_config.edge_threshold,
-    }, ds_pipeline);
-    
-    // start processing the image. Each subsequent render uses the 
-    // result (rendertarget) from the last render.
-    render(RenderDescriptor {
-        pipeline: &dog_shader.pipeline,
-        bind_group: &dog_shader.bind_group,
-        render_target: &dog_shader.render_target,
-        output_buffer: &dog_shader.output_buffer,
-        texture_size: &texture_size,
-    }, &queue, &device);
-
-    render(RenderDescriptor {
-        pipeline: &sobel_shader.pipeline,
-        bind_group: &sobel_shader.bind_group,
-        render_target: &sobel_shader.render_target,
-        output_buffer: &sobel_shader.output_buffer,
-        texture_size: &texture_size,
-    }, &queue, &device);
-
-    // render and store output into output_buffer
-    render_compute(ComputeDescriptor {
-        pipeline: &ds_shader.pipeline,
-        bind_group: &ds_shader.bind_group,
-        storage_buffer: &ds_shader.storage_buffer,
-        output_buffer: &ds_shader.output_buffer,
-        size: &ascii_buffer_size,
-        texture_size: &texture_size,
-    }, &device, &queue, wg_size);
-
-    //// !!! Only uncomment when input is an image !!!
-    //// these  two functions can be used to generate images from each step in the shader program
-    // copy_to_img(&CopyDescriptor {
-    //     output_buffer: &dog_shader.output_buffer,
-    //     texture_size: &texture_size
-    // }, &device).await;
-
-    // copy_to_img(&CopyDescriptor {
-    //     output_buffer: &sobel_shader.output_buffer,
-    //     texture_size: &texture_size
-    // }, &device).await;
-
-    let render_time = benchmark_render.elapsed();
-
-    let benchmark_cache = Instant::now();
-
-    // copy data from output_buffer into a CPU mappable buffer
-    let data = copy_data(&ds_shader.output_buffer, &device).await;
-
-    // append the processed text buffer to the last frame in cache file
-    cache_result(&data, &texture_size, &file, wg_size);
-
-    let cache_time = benchmark_cache.elapsed();
-    let total_elapsed_time = image_decode_time + write_texture_time + render_time + cache_time;
+        // create and compile shaders
+        let preprocess_shader = preprocess_shader::new(
+            device, &image_texture, texture_size, &pipelines.preprocess, shader_config,
+        );
+        let dog_shader = dog_shader::new(
+            device, &preprocess_shader.render_target, texture_size, &pipelines.dog_blur, &pipelines.dog, shader_config,
+        );
+        let sobel_shader = sobel_shader::new(
+            device, &dog_shader.render_target, texture_size, &pipelines.sobel, shader_config,
+        );
+        let ds_shader = downscale_shader::new(downscale_shader::DownscaleShaderStruct {
+            device,
+            texture: &preprocess_shader.render_target,
+            sobel_texture: &sobel_shader.render_target,
+            size: texture_size,
+            buffer_size: &ascii_buffer_size,
+            ascii_style: &shader_config.charset.0,
+
+            draw_edges: shader_config.draw_edges,
+            edge_threshold: shader_config.edge_threshold,
+            bayer_dither: shader_config.dither == utils::Dither::Bayer,
+            shape_glyphs: shader_config.shape_glyphs,
+            temporal: shader_config.temporal as i32,
+            temporal_strength: shader_config.temporal_strength,
+            transparent: shader_config.background == utils::Background::Transparent,
+            history_buffer,
+        }, &pipelines.ds);
+
+        return FrameSlot {
+            image_texture,
+            texture_size,
+            ascii_buffer_size,
+            wg_size,
+            preprocess_shader,
+            dog_shader,
+            sobel_shader,
+            ds_shader,
+            shader_config: shader_config.clone(),
+            dump_stages,
+            stage_buffers: dump_stages.map(|_| StageBuffers::new(device, texture_size)),
+            submission: None,
+            submitted_at: Instant::now(),
+            decode_time: Duration::ZERO,
+            dump_frame: None,
+        };
+    }
 
-    if verbose {
-        println!("Frame processed  wg_size: ({},{}) | Total: {:.5?} | image_decode: {:.5?}, render: {:.5?}, cache: {:.5?}",
-            wg_size.x, wg_size.y, total_elapsed_time, image_decode_time, render_time, cache_time
+    /// Uploads a decoded frame and queues every shader pass for it. Does not wait for the GPU.
+    fn submit(&mut self, desc: &ProcessDescriptor, frame: &DecodedFrame) {
+        let device = &desc.device;
+        let queue = &desc.queue;
+        let texture_size = self.texture_size;
+
+        self.submitted_at = Instant::now();
+        self.decode_time = frame.decode_time;
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.image_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &frame.rgba,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(texture_size.width * 4),
+                rows_per_image: Some(texture_size.height),
+            },
+            texture_size,
         );
+        self.preprocess_shader.write_lut(queue, &frame.rgba);
+
+        // start processing the image. Each subsequent render uses the 
+        // result (rendertarget) from the last render. All passes go into one submission.
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { 
+            label: None,
+        });
+
+        render(RenderDescriptor {
+            pipeline: self.preprocess_shader.pipeline,
+            bind_group: &self.preprocess_shader.bind_group,
+            render_target: &self.preprocess_shader.render_target,
+        }, &mut encoder);
+
+        render(RenderDescriptor {
+            pipeline: self.dog_shader.blur_pipeline,
+            bind_group: &self.dog_shader.blur_bind_group,
+            render_target: &self.dog_shader.blur_target,
+        }, &mut encoder);
+
+        render(RenderDescriptor {
+            pipeline: self.dog_shader.pipeline,
+            bind_group: &self.dog_shader.bind_group,
+            render_target: &self.dog_shader.render_target,
+        }, &mut encoder);
+
+        render(RenderDescriptor {
+            pipeline: self.sobel_shader.pipeline,
+            bind_group: &self.sobel_shader.bind_group,
+            render_target: &self.sobel_shader.render_target,
+        }, &mut encoder);
+
+        // render and store output into output_buffer
+        render_compute(ComputeDescriptor {
+            pipeline: self.ds_shader.pipeline,
+            bind_group: &self.ds_shader.bind_group,
+            storage_buffer: &self.ds_shader.storage_buffer,
+            output_buffer: &self.ds_shader.output_buffer,
+            size: &self.ascii_buffer_size,
+            texture_size: &texture_size,
+        }, &mut encoder, self.wg_size);
+        if self.diffuses_error() {
+            encoder.copy_buffer_to_buffer(
+                &self.ds_shader.luma_storage_buffer, 0, &self.ds_shader.luma_output_buffer, 0, self.ascii_buffer_size,
+            );
+        }
+
+        // keep the intermediate stages of the frames picked with --dump-stages
+        let frame_number = frame.index + 1;
+        self.dump_frame = None;
+        if let Some(dump_stages) = self.dump_stages && dump_stages.frames.contains(&frame_number) {
+            self.stage_buffers.as_ref().unwrap().copy(&mut encoder, [
+                &self.preprocess_shader.render_target,
+                &self.dog_shader.render_target,
+                &self.sobel_shader.render_target,
+            ]);
+            self.dump_frame = Some(frame_number);
+        }
+        let submission = queue.submit(Some(encoder.finish()));
+
+        // the buffer is mapped once the GPU is done with it; `finish` waits for that
+        self.ds_shader.output_buffer.slice(..).map_async(wgpu::MapMode::Read, |result| {
+            result.unwrap();
+        });
+        if self.diffuses_error() {
+            self.ds_shader.luma_output_buffer.slice(..).map_async(wgpu::MapMode::Read, |result| {
+                result.unwrap();
+            });
+        }
+        if self.dump_frame.is_some() {
+            self.stage_buffers.as_ref().unwrap().map();
+        }
+        self.submission = Some(submission);
     }
 
-    return Benchmark {
-        total_time: total_elapsed_time,
-        image_decode_time,
-        render_time,
-        cache_time,
-    };
+    /// Waits for the frame in this slot to finish rendering and appends its glyphs to 
+    /// the cache.
+    fn finish(&mut self, desc: &ProcessDescriptor, cache: &mut frame_cache::CacheWriter, last_levels: &mut Vec<u32>, verbose: bool) -> Benchmark {
+        let submission = self.submission.take().unwrap();
+        desc.device.poll(wgpu::PollType::WaitForSubmissionIndex(submission)).unwrap();
+        let render_time = self.submitted_at.elapsed();
+
+        let benchmark_cache = Instant::now();
+
+        // copy data out of the mapped buffer so the slot can be reused
+        let mut data: Vec<u32> = {
+            let mapped = self.ds_shader.output_buffer.slice(..).get_mapped_range();
+            bytemuck::cast_slice(&mapped).to_vec()
+        };
+        self.ds_shader.output_buffer.unmap();
+
+        if self.diffuses_error() {
+            let luma: Vec<f32> = {
+                let mapped = self.ds_shader.luma_output_buffer.slice(..).get_mapped_range();
+                bytemuck::cast_slice(&mapped).to_vec()
+            };
+            self.ds_shader.luma_output_buffer.unmap();
+            diffuse_error(&mut data, &luma, &self.texture_size, self.wg_size, &self.shader_config, last_levels);
+        }
+
+        // append the processed frame to the cache
+        cache.write_frame(&tile_glyphs(&data, &self.texture_size, self.wg_size, &self.shader_config.charset)).unwrap();
+
+        if let Some(frame_number) = self.dump_frame.take() {
+            self.save_stages(frame_number, &data);
+        }
+
+        let cache_time = benchmark_cache.elapsed();
+        let total_elapsed_time = self.decode_time + render_time + cache_time;
+
+        if verbose {
+            println!("Frame processed  wg_size: ({},{}) | Total: {:.5?} | image_decode: {:.5?}, render: {:.5?}, cache: {:.5?}",
+                self.wg_size.x, self.wg_size.y, total_elapsed_time, self.decode_time, render_time, cache_time
+            );
+        }
+
+        return Benchmark {
+            total_time: total_elapsed_time,
+            image_decode_time: self.decode_time,
+            render_time,
+            cache_time,
+        };
+    }
+}
+
+impl FrameSlot<'_> {
+    fn diffuses_error(&self) -> bool {
+        let dither = self.shader_config.dither;
+        return dither == utils::Dither::FloydSteinberg || dither == utils::Dither::Atkinson;
+    }
+
+    /// Saves the intermediate stages of a frame as `frame_<n>_<stage>.png`
+    fn save_stages(&self, frame_number: usize, data: &[u32]) {
+        let dir = &self.dump_stages.unwrap().dir;
+        let stage_buffers = self.stage_buffers.as_ref().unwrap();
+        let source = stage_buffers.read(&stage_buffers.source, &self.texture_size);
+        let dog = stage_buffers.read(&stage_buffers.dog, &self.texture_size);
+        let sobel = stage_buffers.read(&stage_buffers.sobel, &self.texture_size);
+        let tiles = tile_image(data, &self.texture_size, self.wg_size, &self.shader_config.charset);
+
+        let stages = [("1_source", &source), ("2_dog", &dog), ("3_sobel", &sobel), ("4_tiles", &tiles)];
+        for (stage, img) in stages {
+            let path = dir.join(format!("frame_{:04}_{}.png", frame_number, stage));
+            img.save(&path).expect("Error: Could not save pipeline stage");
+        }
+    }
 }
 
 struct RenderDescriptor<'a> {
     pipeline: &'a wgpu::RenderPipeline,
     bind_group: &'a wgpu::BindGroup,
     render_target: &'a wgpu::Texture,
-    output_buffer: &'a wgpu::Buffer,
-    texture_size: &'a wgpu::Extent3d,
 }
 
 /// Run a fragment shader to process texture
-fn render(desc: RenderDescriptor, queue: &wgpu::Queue, device: &wgpu::Device) {
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { 
-        label: None,
-    });
-
-    {
-        let render_target_view = desc.render_target.create_view(&Default::default());
-        let render_pass_desc = wgpu::RenderPassDescriptor {
-            label: Some("render pass"),
-            color_attachments: &[
-                Some(wgpu::RenderPassColorAttachment {
-                    view: &render_target_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu:: LoadOp::Clear(wgpu::Color {
-                            r:0.0,
-                            g:0.0,
-                            b:0.0,
-                            a:1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })
-            ],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        };
-        let mut pass = encoder.begin_render_pass(&render_pass_desc);
-        pass.set_pipeline(desc.pipeline);
-        pass.set_bind_group(0, desc.bind_group, &[]);
-        pass.draw(0..6,0..1);
-    }
-
-    // only used for last render step before compute shader
-    encoder.copy_texture_to_buffer(
-        wgpu::TexelCopyTextureInfo {
-            aspect: wgpu::TextureAspect::All,
-                    texture: desc.render_target,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        wgpu::TexelCopyBufferInfo {
-            buffer: desc.output_buffer,
-            layout: wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(256 * (4.0 * desc.texture_size.width as f32 / 256.0).ceil() as u32),
-                rows_per_image: Some(desc.texture_size.height),
-            },
-        },
-        *desc.texture_size
-    );
-    queue.submit(Some(encoder.finish()));
+fn render(desc: RenderDescriptor, encoder: &mut wgpu::CommandEncoder) {
+    let render_target_view = desc.render_target.create_view(&Default::default());
+    let render_pass_desc = wgpu::RenderPassDescriptor {
+        label: Some("render pass"),
+        color_attachments: &[
+            Some(wgpu::RenderPassColorAttachment {
+                view: &render_target_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu:: LoadOp::Clear(wgpu::Color {
+                        r:0.0,
+                        g:0.0,
+                        b:0.0,
+                        a:1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        ],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    };
+    let mut pass = encoder.begin_render_pass(&render_pass_desc);
+    pass.set_pipeline(desc.pipeline);
+    pass.set_bind_group(0, desc.bind_group, &[]);
+    pass.draw(0..6,0..1);
 }
 
 struct ComputeDescriptor<'a> {
@@ -592,11 +1532,7 @@ struct ComputeDescriptor<'a> {
 }
 
 /// Run a compute shader to process texture and store result in a buffer
-fn render_compute(desc: ComputeDescriptor, device: &wgpu::Device, queue: &wgpu::Queue, wg_size: WorkgroupSize) {
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { 
-        label: None,
-    });
-
+fn render_compute(desc: ComputeDescriptor, encoder: &mut wgpu::CommandEncoder, wg_size: WorkgroupSize) {
     {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: None,
@@ -622,89 +1558,207 @@ fn render_compute(desc: ComputeDescriptor, device: &wgpu::Device, queue: &wgpu::
            0,
             *desc.size
     );
+}
 
-    queue.submit(Some(encoder.finish()));
+/// Options for `--dump-stages`. Frame numbers start at 1, like the decoded frame files.
+#[derive(Clone)]
+pub struct DumpStages {
+    pub dir: PathBuf,
+    pub frames: Vec<usize>,
 }
 
-// struct CopyDescriptor<'a> {
-//     output_buffer: &'a wgpu::Buffer,
-//     texture_size: &'a wgpu::Extent3d
-// }
+/// Readback copies of the pre-process, DoG and Sobel render targets. Only made when 
+/// stages are dumped.
+struct StageBuffers {
+    source: wgpu::Buffer,
+    dog: wgpu::Buffer,
+    sobel: wgpu::Buffer,
+    bytes_per_row: u32,
+}
 
-/// Optional; Copy texture to generate an image.
-/// Used if you want to see what each step in the shader algorithm does
-// async fn copy_to_img(desc: &CopyDescriptor<'_>, device: &wgpu::Device) {
-//     let buffer_slice = desc.output_buffer.slice(..);
-//     let size = desc.texture_size;
+impl StageBuffers {
+    fn new(device: &wgpu::Device, texture_size: wgpu::Extent3d) -> StageBuffers {
+        // copies from a texture need rows aligned to 256 bytes
+        let bytes_per_row = utils::align_buffer_size(texture_size.width * 4, 256) as u32;
+        let buffer_desc = wgpu::BufferDescriptor {
+            label: Some("stage dump buffer"),
+            size: (bytes_per_row * texture_size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        };
 
-//     let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
-//     buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-//         tx.send(result).unwrap();
-//     });
-//     device.poll(wgpu::PollType::Wait).unwrap();
-//     rx.receive().await.unwrap().unwrap();
+        return StageBuffers {
+            source: device.create_buffer(&buffer_desc),
+            dog: device.create_buffer(&buffer_desc),
+            sobel: device.create_buffer(&buffer_desc),
+            bytes_per_row,
+        };
+    }
 
-//     let data = buffer_slice.get_mapped_range();
+    fn copy(&self, encoder: &mut wgpu::CommandEncoder, textures: [&wgpu::Texture; 3]) {
+        for (texture, buffer) in textures.into_iter().zip([&self.source, &self.dog, &self.sobel]) {
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    aspect: wgpu::TextureAspect::All,
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(self.bytes_per_row),
+                        rows_per_image: Some(texture.height()),
+                    },
+                },
+                texture.size(),
+            );
+        }
+    }
 
-//     // absolutely psychotic way to avoid name collisions
-//     let mut rng = rand::rng();
-//     let rand_num: u32 = rng.random();
+    fn map(&self) {
+        for buffer in [&self.source, &self.dog, &self.sobel] {
+            buffer.slice(..).map_async(wgpu::MapMode::Read, |result| {
+                result.unwrap();
+            });
+        }
+    }
+
+    /// Copy a mapped stage buffer to an image, dropping the row padding
+    fn read(&self, buffer: &wgpu::Buffer, size: &wgpu::Extent3d) -> image::RgbaImage {
+        let mut pixels = Vec::with_capacity((size.width * size.height * 4) as usize);
+        {
+            let mapped = buffer.slice(..).get_mapped_range();
+            for row in mapped.chunks(self.bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..(size.width * 4) as usize]);
+            }
+        }
+        buffer.unmap();
+        return image::RgbaImage::from_raw(size.width, size.height, pixels).unwrap();
+    }
+}
 
-//     let buffer =
-//         ImageBuffer::<Rgba<u8>, _>::from_raw(size.width, size.height, data).unwrap();
-//     buffer.save(format!("example/image_{}.png", rand_num)).unwrap();
+/// Draws every ASCII tile as a block: edges in their Sobel direction color, 
+/// everything else as its quantized luminance.
+fn tile_image(vec: &[u32], tex_size: &wgpu::Extent3d, wg_size: WorkgroupSize, charset: &utils::Charset) -> image::RgbaImage {
+    let padded_row_size = (utils::align_buffer_size_f(tex_size.width,64)/wg_size.x as f32).ceil() as usize;
+    let tiles_x = tex_size.width / wg_size.x;
+    let tiles_y = tex_size.height / wg_size.y;
+    let levels = (charset.0.len() - 1) as u32;
+
+    let mut img = image::RgbaImage::new(tiles_x * wg_size.x, tiles_y * wg_size.y);
+    for y in 0..tiles_y {
+        for x in 0..tiles_x {
+            let index = vec[x as usize + y as usize * padded_row_size];
+            let color = match index {
+                1000 => [255, 0, 0, 255],   // |
+                2000 => [0, 255, 0, 255],   // /
+                3000 => [0, 0, 255, 255],   // _
+                4000 => [255, 255, 0, 255], // \
+                TRANSPARENT => [0, 0, 0, 0],
+                SHAPE_BASE.. => [255, 0, 255, 255],
+                _ => {
+                    let luma = (255 * std::cmp::min(index, levels) / levels) as u8;
+                    [luma, luma, luma, 255]
+                }
+            };
+            for py in 0..wg_size.y {
+                for px in 0..wg_size.x {
+                    img.put_pixel(x * wg_size.x + px, y * wg_size.y + py, image::Rgba(color));
+                }
+            }
+        }
+    }
+    return img;
+}
 
-//     drop(buffer);
-//     desc.output_buffer.unmap();
-// }
+// (x offset, y offset, share of the error) for each error diffusion kernel
+const FLOYD_STEINBERG: [(i32, i32, f32); 4] = [
+    (1, 0, 7.0 / 16.0), (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0),
+];
+// Atkinson only spreads 3/4 of the error, which keeps more contrast
+const ATKINSON: [(i32, i32, f32); 6] = [
+    (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0), (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0), (0, 2, 1.0 / 8.0),
+];
+
+/// Requantizes the luminance tiles with error diffusion. Edge tiles keep their glyph and 
+/// don't take part in the diffusion. With hysteresis, a tile keeps its level from 
+/// `last_levels` like it does in the downscale shader.
+fn diffuse_error(
+    vec: &mut [u32], luma: &[f32], tex_size: &wgpu::Extent3d, wg_size: WorkgroupSize,
+    shader_config: &utils::ShaderConfig, last_levels: &mut Vec<u32>,
+) {
+    let kernel: &[(i32, i32, f32)] = match shader_config.dither {
+        utils::Dither::Atkinson => &ATKINSON,
+        _ => &FLOYD_STEINBERG,
+    };
+    let padded_row_size = (utils::align_buffer_size_f(tex_size.width,64)/wg_size.x as f32).ceil() as usize;
+    let tiles_x = (tex_size.width / wg_size.x) as i32;
+    let tiles_y = (tex_size.height / wg_size.y) as i32;
+    let steps = (shader_config.charset.0.len() - 1) as f32;
+    let hysteresis = shader_config.temporal == utils::Temporal::Hysteresis && last_levels.len() == vec.len();
+
+    let mut error = vec![0.0; vec.len()];
+    for y in 0..tiles_y {
+        for x in 0..tiles_x {
+            let index = x as usize + y as usize * padded_row_size;
+            if vec[index] > 999 {
+                continue;
+            }
 
-/// Copy data from a compute shader output buffer and return it as a `Vec<u32>`
-async fn copy_data(output_buffer: &wgpu::Buffer, device: &wgpu::Device) -> Vec<u32> {
-    let buffer_slice = output_buffer.slice(..);
+            let value = luma[index] + error[index];
+            let mut level = (value * steps).round().clamp(0.0, steps);
+            if hysteresis && last_levels[index] < 1000
+                && (value * steps - last_levels[index] as f32).abs() < 0.5 + shader_config.temporal_strength {
+                level = last_levels[index] as f32;
+            }
+            vec[index] = level as u32;
 
-    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-        tx.send(result).unwrap();
-    });
-    device.poll(wgpu::PollType::Wait).unwrap();
-    rx.receive().await.unwrap().unwrap();
+            let diff = value - level / steps;
+            for (dx, dy, share) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && nx < tiles_x && ny < tiles_y {
+                    error[nx as usize + ny as usize * padded_row_size] += diff * share;
+                }
+            }
+        }
+    }
 
-    let data = buffer_slice.get_mapped_range();
-    let result: Vec<u32> = bytemuck::cast_slice(&data).to_vec();
+    last_levels.clear();
+    last_levels.extend_from_slice(vec);
+}
 
-    return result;
+/// Size of the ascii storage/read buffers: one u32 per tile, rounded up to the 
+/// nearest multiple of 256 to align buffer offset (prevents UnalignedCopyOffset error).
+fn ascii_buffer_size(texture_size: wgpu::Extent3d, wg_size: WorkgroupSize) -> wgpu::BufferAddress {
+    let temp_buff_size = (4.0 * texture_size.width as f32 * texture_size.height as f32 / wg_size.x as f32 / wg_size.y as f32).floor();
+    return (256.0 * (temp_buff_size/256.0).ceil()) as wgpu::BufferAddress;
 }
 
 fn index_string(s: &str, n: u32) -> char {
     return s.chars().nth((n).try_into().unwrap()).unwrap();
 }
 
-/// Read a copy of a compute shader output buffer and cache them to `file`
-fn cache_result(vec: &Vec<u32>, tex_size: &wgpu::Extent3d, mut file: &File, wg_size: WorkgroupSize) {
-    let mut count = 0;
-    let padded_row_size = (utils::align_buffer_size_f(tex_size.width,64)/wg_size.x as f32).ceil();
-    let mut cache_string: String = Default::default();
-    for num in vec.iter() {
-        let char: char;
-        let index: u32 = *num;
-
-        // go to next row
-        if count == padded_row_size as u32 {
-         cache_string += "\n";
-            count = 0;
-        }
-
-        // if edge
-        if index > 999 {
-            char = index_string(ASCII_EDGES, (index / 1000) - 1);
+/// Turns a copy of the compute shader output buffer into the glyph of every tile, row by row. 
+/// The rows of the buffer are padded to the aligned texture width, which is left out.
+fn tile_glyphs(vec: &[u32], tex_size: &wgpu::Extent3d, wg_size: WorkgroupSize, charset: &utils::Charset) -> Vec<u8> {
+    let padded_row_size = (utils::align_buffer_size_f(tex_size.width,64)/wg_size.x as f32).ceil() as usize;
+    let tiles_x = (tex_size.width / wg_size.x) as usize;
+    let tiles_y = (tex_size.height / wg_size.y) as usize;
+
+    let mut cells = Vec::with_capacity(tiles_x * tiles_y);
+    for y in 0..tiles_y {
+        for index in &vec[y * padded_row_size..y * padded_row_size + tiles_x] {
+            let index = *index;
+            // if shape glyph, transparent or edge
+            let char =
+                if index >= SHAPE_BASE {index_string(downscale_shader::SHAPE_GLYPHS, index - SHAPE_BASE)}
+                else if index == TRANSPARENT {' '}
+                else if index > 999 {index_string(ASCII_EDGES, (index / 1000) - 1)}
+                else {index_string(&charset.0, index)};
+            cells.push(char as u8);
         }
-        else {
-            char = ASCII_STYLE.chars().nth((*num).try_into().unwrap()).unwrap();
-        }
-
-        cache_string += &char.to_string();
-        count += 1;
     }
-
-    file.write(format!("\n{}\n", cache_string).as_bytes()).ok();
-}
\ No newline at end of file
+    return cells;
+}
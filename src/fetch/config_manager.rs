@@ -1,7 +1,20 @@
 //! Scripts responsible for serializing and deserializing the mofetfch config file
 
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::paths;
+
+/// Version of the config layout. Configs of older versions are migrated when they are 
+/// loaded. Version 1 configs have no `config_version`, but neither do configs written by 
+/// hand, so a config without one is only migrated when it has something to migrate.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// The default luminance ramp, which looks good on most terminal fonts
+pub const DEFAULT_CHARSET: &str = " .,:?c79WNB@";
+
 /// default toml configuration
 const PROGRAM_DEFAULTS: &str = r#"
+config_version = 2
 include_order = ["host_name","os","kernel","cpu","gpu","linebreak","cpu_usage","uptime"]
 
 [key_names]
@@ -20,21 +33,78 @@ cpu = "system_default"
 gpu = "system_default"
 
 [options_defaults]
-input = "None"
+# input = "~/Pictures/thumbnail.gif"
+# input_dir = "~/Pictures/mofetch"
+random = false
 fps = 24
 brightness = 1.1
 contrast = 1.1
+gamma = 1.0
+saturation = 1.0
+invert = false
+equalize = "none"
+sharpen = 0.0
+background = "transparent"
 draw_edges = true
 edge_threshold = 0.3
+charset = " .,:?c79WNB@"
+dither = "none"
+shape_glyphs = false
+temporal = "none"
+temporal_strength = 0.5
+sigma = 3.0
+sigma_ratio = 4.333
+# kernel_radius = 32
+dog_threshold = 0.02
+tau = 1.0
+phi = 0.0
+edge_bin_width = 0.0625
 overwrite_cache = false
+cache_max_size = "2GiB"
 max_width = 0.7
 max_height = 1.0
+fit = "contain"
+# crop = "center-square"
+# target_cols = 40
+# target_rows = 20
 adapter_index = 0
 hide_info = false
 verbose = false
+reprocess_on_resize = false
+progressive = false
+progressive_frames = 24
+speed = 1.0
+ping_pong = false
+# loops = 1
+# duration = 5.0
+# start = "0:00"
+# end = "0:10"
+# dump_stages = "stages"
+# dump_frames = [1]
 "#;
 
+// each migration rewrites a config of the version at its index + 1 to the next version. The
+// text is edited rather than re-serialized, so the comments and formatting of the user are kept
+const MIGRATIONS: [fn(&str) -> String; 1] = [migrate_v1];
+
+/// Version 1 wrote `input = "None"` as the placeholder for no input, which was read as a 
+/// file called "None". It is commented out, so a missing input is reported as such.
+fn migrate_v1(config_str: &str) -> String {
+    let mut migrated: String = config_str.lines()
+        .map(|line| {
+            let key_value: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+            if key_value == "input=\"None\"" {format!("# {}", line)} else {String::from(line)}
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if config_str.ends_with('\n') {
+        migrated.push('\n');
+    }
+    return migrated;
+}
+
 #[derive(serde::Deserialize)]
+#[serde(default)]
 pub struct KeyNames {
     pub os: String,
     pub kernel: String,
@@ -45,6 +115,7 @@ pub struct KeyNames {
 }
 
 #[derive(serde::Deserialize)]
+#[serde(default)]
 pub struct KeyValues {
     pub host_name: String,
     pub os: String,
@@ -53,43 +124,708 @@ pub struct KeyValues {
     pub gpu: String,
 }
 
+impl Default for KeyNames {
+    fn default() -> Self {
+        return KeyNames {
+            os: String::from("OS"),
+            kernel: String::from("Kernel"),
+            cpu: String::from("CPU"),
+            gpu: String::from("GPU"),
+            cpu_usage: String::from("CPU usage"),
+            uptime: String::from("Uptime"),
+        };
+    }
+}
+
+impl Default for KeyValues {
+    fn default() -> Self {
+        let system_default = || String::from("system_default");
+        return KeyValues {
+            host_name: system_default(),
+            os: system_default(),
+            kernel: system_default(),
+            cpu: system_default(),
+            gpu: system_default(),
+        };
+    }
+}
+
+/// Every field has a default, so a config only needs the settings that differ from 
+/// `PROGRAM_DEFAULTS`
 #[derive(serde::Deserialize)]
 pub struct OptionsDefaults {
     pub input: Option<String>,
+    /// media directory that `random` picks the input from
+    #[serde(default)]
+    pub input_dir: Option<String>,
+    #[serde(default)]
+    pub random: bool,
+    #[serde(default = "default_fps")]
     pub fps: u16,
+    #[serde(default = "default_brightness")]
     pub brightness: f32,
+    #[serde(default = "default_contrast")]
     pub contrast: f32,
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    #[serde(default = "default_saturation")]
+    pub saturation: f32,
+    #[serde(default)]
+    pub invert: bool,
+    #[serde(default = "default_equalize")]
+    pub equalize: String,
+    #[serde(default)]
+    pub sharpen: f32,
+    #[serde(default = "default_background")]
+    pub background: String,
+    #[serde(default = "default_draw_edges")]
     pub draw_edges: bool,
+    #[serde(default = "default_edge_threshold")]
     pub edge_threshold: f32,
+    /// luminance ramp from the darkest to the brightest glyph
+    #[serde(default = "default_charset")]
+    pub charset: String,
+    #[serde(default = "default_dither")]
+    pub dither: String,
+    #[serde(default)]
+    pub shape_glyphs: bool,
+    #[serde(default = "default_temporal")]
+    pub temporal: String,
+    #[serde(default = "default_temporal_strength")]
+    pub temporal_strength: f32,
+    #[serde(default = "default_sigma")]
+    pub sigma: f32,
+    #[serde(default = "default_sigma_ratio")]
+    pub sigma_ratio: f32,
+    /// derived from the sigmas when it isn't set
+    #[serde(default)]
+    pub kernel_radius: Option<u32>,
+    #[serde(default = "default_dog_threshold")]
+    pub dog_threshold: f32,
+    #[serde(default = "default_tau")]
+    pub tau: f32,
+    #[serde(default)]
+    pub phi: f32,
+    #[serde(default = "default_edge_bin_width")]
+    pub edge_bin_width: f32,
+    #[serde(default)]
     pub overwrite_cache: bool,
+    #[serde(default = "default_cache_max_size")]
+    pub cache_max_size: String,
+    #[serde(default = "default_max_width")]
     pub max_width: f32,
+    #[serde(default = "default_max_height")]
     pub max_height: f32,
+    #[serde(default = "default_fit")]
+    pub fit: String,
+    #[serde(default)]
+    pub crop: Option<String>,
+    #[serde(default)]
+    pub target_cols: Option<u16>,
+    #[serde(default)]
+    pub target_rows: Option<u16>,
+    #[serde(default)]
     pub adapter_index: usize,
+    #[serde(default)]
     pub hide_info: bool,
+    #[serde(default)]
     pub verbose: bool,
+    #[serde(default)]
+    pub reprocess_on_resize: bool,
+    #[serde(default)]
+    pub progressive: bool,
+    #[serde(default = "default_progressive_frames")]
+    pub progressive_frames: usize,
+    #[serde(default)]
+    pub loops: Option<u32>,
+    #[serde(default)]
+    pub duration: Option<f32>,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub ping_pong: bool,
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>,
+    #[serde(default)]
+    pub dump_stages: Option<std::path::PathBuf>,
+    #[serde(default)]
+    pub dump_frames: Option<Vec<usize>>,
+    /// the profile the options were laid over, from `--profile` or `[inputs]`
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// whether the input was given on the command line, rather than taken from the config
+    #[serde(skip)]
+    pub input_given: bool,
+}
+
+impl Default for OptionsDefaults {
+    fn default() -> Self {
+        // every field has a serde default
+        return toml::from_str("").unwrap();
+    }
+}
+
+fn default_fps() -> u16 {
+    24
+}
+
+fn default_brightness() -> f32 {
+    1.1
+}
+
+fn default_contrast() -> f32 {
+    1.1
+}
+
+fn default_draw_edges() -> bool {
+    true
+}
+
+fn default_edge_threshold() -> f32 {
+    0.3
+}
+
+fn default_charset() -> String {
+    String::from(DEFAULT_CHARSET)
+}
+
+fn default_max_width() -> f32 {
+    0.7
+}
+
+fn default_max_height() -> f32 {
+    1.0
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+fn default_progressive_frames() -> usize {
+    24
+}
+
+fn default_cache_max_size() -> String {
+    String::from("2GiB")
+}
+
+fn default_fit() -> String {
+    String::from("contain")
+}
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+fn default_saturation() -> f32 {
+    1.0
+}
+
+fn default_equalize() -> String {
+    String::from("none")
+}
+
+fn default_background() -> String {
+    String::from("transparent")
+}
+
+fn default_dither() -> String {
+    String::from("none")
+}
+
+fn default_temporal() -> String {
+    String::from("none")
+}
+
+fn default_temporal_strength() -> f32 {
+    0.5
+}
+
+fn default_sigma() -> f32 {
+    3.0
+}
+
+fn default_sigma_ratio() -> f32 {
+    4.333
+}
+
+fn default_dog_threshold() -> f32 {
+    0.02
+}
+
+fn default_tau() -> f32 {
+    1.0
+}
+
+fn default_edge_bin_width() -> f32 {
+    0.0625
 }
 
 #[derive(serde::Deserialize)]
 pub struct Config {
+    #[serde(default = "default_config_version")]
+    pub config_version: u32,
+    #[serde(default = "default_include_order")]
     pub include_order: Vec<String>,
+    #[serde(default)]
     pub key_names: KeyNames,
+    #[serde(default)]
     pub key_values: KeyValues,
+    #[serde(default)]
     pub options_defaults: OptionsDefaults,
+    /// `[profiles.<name>]` tables of options and info layout that are laid over the defaults
+    #[serde(default)]
+    pub profiles: toml::Table,
+    /// input paths or globs, mapped to the profile they are played with
+    #[serde(default)]
+    pub inputs: BTreeMap<String, String>,
+}
+
+fn default_config_version() -> u32 {
+    CONFIG_VERSION
 }
 
-pub fn retrieve_config() -> Config {
-    let config_dir = format!("{}/mofetch",dirs::config_dir().unwrap().to_str().unwrap());
-    let config_path = format!("{}/config.toml",&config_dir);
-    let config_exists = std::path::Path::new(&config_path).exists();
+fn default_include_order() -> Vec<String> {
+    ["host_name","os","kernel","cpu","gpu","linebreak","cpu_usage","uptime"].into_iter().map(String::from).collect()
+}
+
+/// Writes the default config, unless there already is a config file. Returns whether it 
+/// was written.
+pub fn init_config() -> std::io::Result<bool> {
+    let config_path = paths::config_path();
+    if config_path.exists() {
+        return Ok(false);
+    }
+    if let Some(config_dir) = config_path.parent() {
+        std::fs::create_dir_all(config_dir)?;
+    }
+    std::fs::write(&config_path, PROGRAM_DEFAULTS)?;
+    return Ok(true);
+}
 
-    let config_str = if !config_exists {
-        std::fs::create_dir(&config_dir).unwrap();
-        std::fs::write(&config_path, PROGRAM_DEFAULTS).unwrap();
+/// `line 3, column 7` for a byte offset into a config
+fn position(config_str: &str, offset: usize) -> String {
+    let before = &config_str[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    return format!("line {}, column {}", before.matches('\n').count() + 1, before[line_start..].chars().count() + 1);
+}
+
+/// The keys a config may have, by table: the keys of `PROGRAM_DEFAULTS`, including the 
+/// commented out ones. The keys of `[profiles]` and `[inputs]` are names, so those tables 
+/// are not checked here.
+fn known_keys() -> toml::Table {
+    let defaults: Vec<&str> = PROGRAM_DEFAULTS.lines()
+        .map(|line| line.strip_prefix("# ").filter(|line| line.contains(" = ")).unwrap_or(line))
+        .collect();
+    let mut known: toml::Table = toml::from_str(&defaults.join("\n")).unwrap();
+    for table in ["profiles", "inputs"] {
+        known.insert(String::from(table), toml::Value::Boolean(true));
+    }
+    return known;
+}
+
+/// The byte offset of the value at `path` in a config, e.g. `["options_defaults", "fps"]`
+fn value_offset(table: &toml::de::DeTable, path: &[&str]) -> Option<usize> {
+    let (first, rest) = path.split_first()?;
+    let (_, value) = table.iter().find(|(key, _)| key.get_ref() == first)?;
+    if rest.is_empty() {
+        return Some(value.span().start);
+    }
+    return value_offset(value.get_ref().as_table()?, rest);
+}
+
+/// Finds the keys of `table` that aren't in `known`, as those are ignored. Returns their 
+/// names with their offset into the config.
+fn unknown_keys(table: &toml::de::DeTable, known: &toml::Table, prefix: &str, unknown: &mut Vec<(usize, String)>) {
+    for (key, value) in table.iter() {
+        let name = format!("{}{}", prefix, key.get_ref());
+        match (known.get(key.get_ref().as_ref()), value.get_ref()) {
+            (None, _) => unknown.push((key.span().start, name)),
+            (Some(toml::Value::Table(known)), toml::de::DeValue::Table(table)) => {
+                unknown_keys(table, known, &format!("{}.", name), unknown);
+            }
+            _ => {}
+        }
+    }
+}
+
+// the keys of a profile that set the info layout rather than an option
+const LAYOUT_KEYS: [&str; 3] = ["include_order", "key_names", "key_values"];
+
+/// The `[profiles]` of a config with the settings of each profile as `T`. The types of the 
+/// settings of the profiles are checked with the config itself, so errors point at them.
+#[derive(serde::Deserialize)]
+struct Profiles<T> {
+    #[serde(default)]
+    profiles: BTreeMap<String, T>,
+}
+
+/// The info layout settings of a profile
+#[derive(serde::Deserialize, Default)]
+#[allow(dead_code)]
+struct ProfileLayout {
+    include_order: Option<Vec<String>>,
+    key_names: Option<KeyNames>,
+    key_values: Option<KeyValues>,
+}
+
+/// Parses a config with the settings of `[profiles.<profile>]` laid over its defaults
+fn parse_profile(config_str: &str, profile: &str) -> Result<Config, String> {
+    let mut document: toml::Table = toml::from_str(config_str).map_err(|error| error.to_string())?;
+    let Some(toml::Value::Table(settings)) = document.get("profiles").and_then(|profiles| profiles.get(profile)).cloned() else {
+        let profiles = document.get("profiles").and_then(|profiles| profiles.as_table())
+            .map(|profiles| profiles.keys().cloned().collect::<Vec<String>>().join(", "))
+            .filter(|profiles| !profiles.is_empty());
+        return Err(match profiles {
+            Some(profiles) => format!("unknown profile \"{}\" (the config has {})", profile, profiles),
+            None => format!("unknown profile \"{}\" (the config has no [profiles])", profile),
+        });
+    };
+
+    for (key, value) in settings {
+        let table = if LAYOUT_KEYS.contains(&key.as_str()) {&mut document} else {
+            let options = document.entry("options_defaults").or_insert(toml::Value::Table(toml::Table::new()));
+            options.as_table_mut().ok_or("options_defaults is not a table")?
+        };
+        // the names and values of the info are merged with the ones of the config
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(value)) => table.extend(value),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+    return toml::Value::Table(document).try_into().map_err(|error: toml::de::Error| error.to_string());
+}
+
+/// Checks the options that are stored as strings. Returns the key of the first invalid 
+/// option with its error.
+fn check_options(options: &OptionsDefaults) -> Option<(&'static str, String)> {
+    use crate::core::{self, utils};
+
+    let checks = [
+        ("equalize", options.equalize.parse::<utils::Equalize>().err()),
+        ("background", options.background.parse::<utils::Background>().err()),
+        ("charset", options.charset.parse::<utils::Charset>().err()),
+        ("dither", options.dither.parse::<utils::Dither>().err()),
+        ("temporal", options.temporal.parse::<utils::Temporal>().err()),
+        ("fit", options.fit.parse::<core::Fit>().err()),
+        ("crop", options.crop.as_ref().and_then(|crop| crop.parse::<core::Crop>().err())),
+        ("cache_max_size", options.cache_max_size.parse::<crate::cache_manager::ByteSize>().err()),
+        ("kernel_radius", options.kernel_radius.filter(|radius| *radius > core::MAX_KERNEL_RADIUS)
+            .map(|_| format!("kernel_radius can be at most {}", core::MAX_KERNEL_RADIUS))),
+    ];
+    return checks.into_iter().find_map(|(key, error)| Some((key, error?)));
+}
+
+/// Parses a config, checking the settings that are stored as strings and every profile as 
+/// well. Errors and warnings point at the line and column of the setting. Returns the 
+/// config with the warnings, e.g. for unknown keys.
+pub fn parse_config(config_str: &str) -> Result<(Config, Vec<String>), String> {
+    let config: Config = toml::from_str(config_str).map_err(|error| error.to_string())?;
+    // the config parsed above, so it parses again with the spans of its keys
+    let document = toml::de::DeTable::parse(config_str).map_err(|error| error.to_string())?;
+    let document = document.get_ref();
+    let at = |path: &[&str], message: String| match value_offset(document, path) {
+        Some(offset) => format!("{}: {}", position(config_str, offset), message),
+        None => message,
+    };
+
+    if let Some((key, error)) = check_options(&config.options_defaults) {
+        return Err(at(&["options_defaults", key], error));
+    }
+    let profile_options = toml::from_str::<Profiles<OptionsDefaults>>(config_str).map_err(|error| error.to_string())?;
+    toml::from_str::<Profiles<ProfileLayout>>(config_str).map_err(|error| error.to_string())?;
+    for (name, options) in profile_options.profiles.iter() {
+        if let Some((key, error)) = check_options(options) {
+            return Err(at(&["profiles", name, key], error));
+        }
+    }
+
+    let mut unknown = Vec::new();
+    let mut known = known_keys();
+    unknown_keys(document, &known, "", &mut unknown);
+    // a profile has the keys of the options and the info layout
+    let mut profile_known = known.remove("options_defaults").and_then(|options| options.as_table().cloned()).unwrap_or_default();
+    profile_known.extend(known.into_iter().filter(|(key, _)| LAYOUT_KEYS.contains(&key.as_str())));
+    let profiles = document.iter().find(|(key, _)| key.get_ref() == "profiles").and_then(|(_, profiles)| profiles.get_ref().as_table());
+    for (name, profile) in profiles.into_iter().flatten() {
+        if let Some(profile) = profile.get_ref().as_table() {
+            unknown_keys(profile, &profile_known, &format!("profiles.{}.", name.get_ref()), &mut unknown);
+        }
+    }
+    unknown.sort();
+    let mut warnings: Vec<String> = unknown.into_iter()
+        .map(|(offset, name)| format!("{}: unknown key `{}` is ignored", position(config_str, offset), name))
+        .collect();
+
+    for (pattern, profile) in config.inputs.iter() {
+        if !config.profiles.contains_key(profile) {
+            warnings.push(at(&["inputs", pattern], format!("[inputs] maps \"{}\" to the unknown profile \"{}\"", pattern, profile)));
+        }
+    }
+    if config.config_version > CONFIG_VERSION {
+        warnings.push(format!("config_version {} is newer than this version of mofetch supports ({}), settings it doesn't know are ignored",
+            config.config_version, CONFIG_VERSION));
+    }
+    return Ok((config, warnings));
+}
+
+/// Expands a leading `~/` of a path from the config to the home directory
+pub fn expand_home(path: &str) -> std::path::PathBuf {
+    return match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => std::path::PathBuf::from(path),
+    };
+}
+
+/// Matches `text` against a glob, where `*` matches within a path component, `**` across 
+/// components and `?` a single character
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    return match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|i| *i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => text.first().is_some_and(|c| *c != b'/') && glob_match(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    };
+}
+
+/// The profile `[inputs]` maps an input to. Patterns with a `/` are matched against the 
+/// path of the input, the others against its file name. When several patterns match, the 
+/// longest one wins.
+pub fn input_profile(config: &Config, input: &str) -> Option<String> {
+    let path = std::path::Path::new(input);
+    let absolute = std::path::absolute(path).ok();
+    let paths: Vec<&str> = [path.to_str(), absolute.as_ref().and_then(|path| path.to_str())].into_iter().flatten().collect();
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or(input);
+
+    let mut matched: Option<(&String, &String)> = None;
+    for (pattern, profile) in config.inputs.iter() {
+        let expanded = expand_home(pattern);
+        let expanded = expanded.as_os_str().as_encoded_bytes();
+        let is_match =
+            if expanded.contains(&b'/') {paths.iter().any(|path| glob_match(expanded, path.as_bytes()))}
+            else {glob_match(expanded, file_name.as_bytes())};
+        if is_match && matched.is_none_or(|(longest, _)| pattern.len() > longest.len()) {
+            matched = Some((pattern, profile));
+        }
+    }
+    return matched.map(|(_, profile)| profile.clone());
+}
+
+/// Brings a config of an older version up to `CONFIG_VERSION`. Returns the version it was 
+/// migrated from and the migrated config, or `None` if it is up to date or doesn't parse.
+pub fn migrate_config(config_str: &str) -> Option<(u32, String)> {
+    let table: toml::Table = toml::from_str(config_str).ok()?;
+    let versioned = table.contains_key("config_version");
+    let version = match table.get("config_version") {
+        Some(version) => u32::try_from(version.as_integer()?).ok()?,
+        None => 1,
+    };
+    if version == 0 || version >= CONFIG_VERSION {
+        return None;
+    }
+
+    let mut migrated = String::from(config_str);
+    for migration in MIGRATIONS[version as usize - 1..].iter() {
+        migrated = migration(&migrated);
+    }
+    // without a version, a config the migrations leave alone is taken to be current
+    if !versioned && migrated == config_str {
+        return None;
+    }
+    let version_line = format!("config_version = {}", CONFIG_VERSION);
+    migrated = match migrated.lines().find(|line| line.trim_start().starts_with("config_version")) {
+        Some(line) => migrated.replacen(line, &version_line, 1),
+        None => format!("{}\n{}", version_line, migrated),
+    };
+    return Some((version, migrated));
+}
+
+// the config is loaded several times per run, but its warnings are only printed once
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Loads the config with the settings of `profile` laid over its defaults, writing the 
+/// defaults first if there is no config and migrating it if it is of an older version. A 
+/// config with errors is reported with the line and column of the error, and mofetch exits.
+pub fn retrieve_config(profile: Option<&str>) -> Config {
+    let config_path = paths::config_path();
+    let config_exists = config_path.exists();
+
+    let mut config_str = if !config_exists {
+        // without a writable config directory, mofetch still runs with the defaults
+        if let Err(error) = init_config() && !WARNED.load(Ordering::Relaxed) {
+            eprintln!("Warning: could not write the default config to {}: {}", config_path.display(), error);
+        }
         String::from(PROGRAM_DEFAULTS)
     }
     else {
-        std::fs::read_to_string(&config_path).unwrap()
+        std::fs::read_to_string(&config_path).unwrap_or_else(|error| {
+            println!("Error: could not read {}: {}", config_path.display(), error);
+            std::process::exit(0);
+        })
     };
-    let config: Config = toml::from_str(&config_str.as_str()).unwrap();
+
+    if let Some((version, migrated)) = migrate_config(&config_str) {
+        // the old config is kept next to the new one
+        let backup_path = paths::with_suffix(&config_path, &format!(".v{}.bak", version));
+        if std::fs::copy(&config_path, &backup_path).is_ok() && std::fs::write(&config_path, &migrated).is_ok() {
+            eprintln!("Migrated {} from config version {} to {}. The old config was saved to {}", config_path.display(), version, CONFIG_VERSION, backup_path.display());
+        }
+        config_str = migrated;
+    }
+
+    let (config, warnings) = parse_config(&config_str).unwrap_or_else(|error| {
+        println!("Error in {}: {}", config_path.display(), error);
+        std::process::exit(0);
+    });
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        for warning in warnings.iter() {
+            eprintln!("Warning in {}: {}", config_path.display(), warning);
+        }
+    }
+    let Some(profile) = profile else {
+        return config;
+    };
+    let mut config = parse_profile(&config_str, profile).unwrap_or_else(|error| {
+        println!("Error: {}", error);
+        std::process::exit(0);
+    });
+    config.options_defaults.profile = Some(String::from(profile));
     return config;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_v1_input() {
+        let cases = [
+            ("input = \"None\"\n", "# input = \"None\"\n"),
+            ("  input=\"None\"", "#   input=\"None\""),
+            ("input = \"~/clip.gif\"\n", "input = \"~/clip.gif\"\n"),
+            ("# input = \"None\"\n", "# input = \"None\"\n"),
+            ("fps = 24\ninput = \"None\" \nrandom = false\n", "fps = 24\n# input = \"None\" \nrandom = false\n"),
+        ];
+        for (config, migrated) in cases {
+            assert_eq!(migrate_v1(config), migrated, "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn migrate() {
+        let cases = [
+            // version 1 configs have no version
+            ("[options_defaults]\ninput = \"None\"\n", Some((1, "config_version = 2\n[options_defaults]\n# input = \"None\"\n"))),
+            ("config_version = 1\n[options_defaults]\ninput = \"None\"\n", Some((1, "config_version = 2\n[options_defaults]\n# input = \"None\"\n"))),
+            ("config_version = 1\nfps = 24\n", Some((1, "config_version = 2\nfps = 24\n"))),
+            // configs without a version and without version 1 settings are current
+            ("[options_defaults]\nfps = 30\n", None),
+            ("", None),
+            ("config_version = 2\n[options_defaults]\ninput = \"None\"\n", None),
+            ("config_version = 3\n", None),
+            ("config_version = 0\n", None),
+            ("config_version = -1\n", None),
+            ("config_version = \"1\"\n", None),
+            ("not toml", None),
+        ];
+        for (config, migrated) in cases {
+            let expected = migrated.map(|(version, config)| (version, String::from(config)));
+            assert_eq!(migrate_config(config), expected, "{:?}", config);
+        }
+        assert_eq!(migrate_config(PROGRAM_DEFAULTS), None);
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let (config, warnings) = parse_config(PROGRAM_DEFAULTS).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!(config.options_defaults.kernel_radius, None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("[options_defaults]\nfps = 30\ndither = \"nope\"\n", "line 3, column 10: "),
+            ("[options_defaults]\nkernel_radius = 33\n", "line 2, column 17: kernel_radius can be at most 32"),
+            ("[profiles.dark]\nbrightness = 0.5\nfit = \"nope\"\n", "line 3, column 7: "),
+            ("[options_defaults]\nfps = \"fast\"\n", "TOML parse error at line 2, column 7"),
+        ];
+        for (config, error) in cases {
+            let actual = parse_config(config).err().unwrap();
+            assert!(actual.starts_with(error), "{:?} gave {:?}", config, actual);
+        }
+    }
+
+    #[test]
+    fn parse_warnings() {
+        let cases: [(&str, &[&str]); 5] = [
+            ("[options_defaults]\nfps = 30\n", &[]),
+            // the commented out defaults are known keys as well
+            ("[options_defaults]\ninput = \"clip.gif\"\nkernel_radius = 8\n", &[]),
+            ("colour = 1\n[options_defaults]\nfsp = 30\n[profiles.dark]\nbrightnes = 0.5\ninclude_order = []\n", &[
+                "line 1, column 1: unknown key `colour` is ignored",
+                "line 3, column 1: unknown key `options_defaults.fsp` is ignored",
+                "line 5, column 1: unknown key `profiles.dark.brightnes` is ignored",
+            ]),
+            ("[inputs]\n\"*.gif\" = \"anime\"\n", &["line 2, column 11: [inputs] maps \"*.gif\" to the unknown profile \"anime\""]),
+            ("config_version = 3\n", &["config_version 3 is newer than this version of mofetch supports (2), settings it doesn't know are ignored"]),
+        ];
+        for (config, expected) in cases {
+            let (_, warnings) = parse_config(config).unwrap();
+            assert_eq!(warnings, expected, "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn glob() {
+        let cases = [
+            ("*.gif", "clip.gif", true),
+            ("*.gif", "clip.mp4", false),
+            ("*.gif", "dir/clip.gif", false),
+            ("clip?.gif", "clip1.gif", true),
+            ("clip?.gif", "clip.gif", false),
+            ("a?b", "a/b", false),
+            ("/media/*/clip.gif", "/media/anime/clip.gif", true),
+            ("/media/*/clip.gif", "/media/anime/old/clip.gif", false),
+            ("/media/**/clip.gif", "/media/anime/old/clip.gif", true),
+            ("/media/**", "/media/anime/clip.gif", true),
+            ("**", "", true),
+            ("", "", true),
+            ("", "a", false),
+            ("clip.gif", "clip.gif", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob_match(pattern.as_bytes(), text.as_bytes()), expected, "{} against {}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn input_profiles() {
+        let config_str = "[profiles.anime]\n[profiles.music]\n[profiles.clips]\n[profiles.intro]\n\
+            [inputs]\n\"*.gif\" = \"anime\"\n\"/media/music/**\" = \"music\"\n\"**/clips/*\" = \"clips\"\n\"**/clips/intro*.gif\" = \"intro\"\n";
+        let (config, _) = parse_config(config_str).unwrap();
+        let cases = [
+            ("clip.gif", Some("anime")),
+            ("/somewhere/clip.gif", Some("anime")),
+            ("clip.mp4", None),
+            ("/media/music/live/song.mp4", Some("music")),
+            // the longest matching pattern wins
+            ("/media/music/cover.gif", Some("music")),
+            ("/home/me/clips/intro.gif", Some("intro")),
+            ("/home/me/clips/outro.gif", Some("clips")),
+            // relative inputs are also matched by their absolute path
+            ("clips/outro.mp4", Some("clips")),
+        ];
+        for (input, profile) in cases {
+            assert_eq!(input_profile(&config, input).as_deref(), profile, "{}", input);
+        }
+    }
+}
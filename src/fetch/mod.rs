@@ -4,10 +4,15 @@
 pub mod config_manager;
 
 use sysinfo::{System};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub fn get_config_defaults() -> config_manager::OptionsDefaults {
-    return config_manager::retrieve_config().options_defaults;
+use crate::core::SharedLayout;
+
+/// The options of the config, with the ones of `profile` laid over them
+pub fn get_config_defaults(profile: Option<&str>) -> config_manager::OptionsDefaults {
+    return config_manager::retrieve_config(profile).options_defaults;
 }
 
 fn get_uptime() -> String {
@@ -128,28 +133,56 @@ fn get_static_info(config: &config_manager::Config, sys: &System, gpu: &wgpu::Ad
 }
 
 // WIP
-pub fn sys_info_manager(gpu: wgpu::AdapterInfo, ascii_w: u32, ascii_h: u32) {
-    let config = config_manager::retrieve_config();
+pub fn sys_info_manager(gpu: wgpu::AdapterInfo, shared_layout: Arc<SharedLayout>, profile: Option<String>) {
+    let config = config_manager::retrieve_config(profile.as_deref());
     let mut sys = System::new_all();
     let static_info = get_static_info(&config, &sys, &gpu);
 
     let info_array = static_info.as_array();
 
-    for (i,data) in info_array.iter().enumerate() {
-        let name_string: String =
-            if data.omit_name {String::from("")}
-            else {format!("{}: ", data.name)};
-        
-        println!("{}{}{}",termion::cursor::Goto((ascii_w + 2) as u16, (i + 2) as u16),
-            name_string, data.value);
-    }
+    let mut drawn_generation = 0;
+    let mut last_refresh: Option<Instant> = None;
+    let mut cpu_usage = 0.0;
+    let mut uptime = get_uptime();
 
     loop {
-        std::thread::sleep(Duration::from_millis(1000));
-        sys.refresh_cpu_usage();
-        let cpu_usage = sys.global_cpu_usage();
-        let uptime = get_uptime();
-        println!("{}{}: {:.3}%  ",termion::cursor::Goto((ascii_w + 2) as u16, 8), &config.key_names.cpu_usage, cpu_usage);
-        println!("{}{}: {}",termion::cursor::Goto((ascii_w + 2) as u16, 9), &config.key_names.uptime, uptime);
+        let generation = shared_layout.generation.load(Ordering::Relaxed);
+        let layout = *shared_layout.layout.lock().unwrap();
+
+        // the playback loop has not placed the thumbnail yet
+        if generation == 0 {
+            std::thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+
+        let refresh = last_refresh.is_none_or(|time| time.elapsed() >= Duration::from_millis(1000));
+        if refresh {
+            sys.refresh_cpu_usage();
+            cpu_usage = sys.global_cpu_usage();
+            uptime = get_uptime();
+            last_refresh = Some(Instant::now());
+        }
+
+        // redraw everything at the new position after a resize
+        let relayout = generation != drawn_generation;
+        if relayout {
+            for (i,data) in info_array.iter().enumerate() {
+                let name_string: String =
+                    if data.omit_name {String::from("")}
+                    else {format!("{}: ", data.name)};
+
+                println!("{}{}{}",termion::cursor::Goto(layout.info_col, layout.info_row + i as u16),
+                    name_string, data.value);
+            }
+            drawn_generation = generation;
+        }
+
+        if refresh || relayout {
+            println!("{}{}: {:.3}%  ",termion::cursor::Goto(layout.info_col, layout.info_row + 6), &config.key_names.cpu_usage, cpu_usage);
+            println!("{}{}: {}",termion::cursor::Goto(layout.info_col, layout.info_row + 7), &config.key_names.uptime, uptime);
+        }
+        shared_layout.drawn_generation.store(generation, Ordering::Relaxed);
+
+        std::thread::sleep(Duration::from_millis(50));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,824 @@
+//! Reads and writes the frame buffer caches (.afb/.sfb).
+//!
+//! A cache is a header followed by one record per frame. All numbers are little endian.
+//!
+//! - header: the magic `MOFB`, the format version (u8), fps (f64), cols (u32) and rows (u32)
+//! - frame record: its kind (u8), the payload length (u32) and the zstd compressed payload
+//! - index: the offset (u64) and kind (u8) of every frame record, followed by the offset of
+//!   the index (u64), the number of frames (u32) and the magic `MOFI`
+//!
+//! The payload of a key frame is every cell of the frame, row by row. The payload of a delta
+//! frame only holds the cells that changed since the previous frame, as runs of
+//! (cells skipped, run length, cells) with both counts written as LEB128 varints. A key frame
+//! is written every `KEYFRAME_INTERVAL` frames, and whenever a delta would not be smaller.
+//!
+//! Playback opens a cache once and decodes frames as they are needed, from the closest key
+//! frame through the index. The index is found from the end of the file, so opening a cache
+//! takes as long no matter how many frames it has. Caches without an index are indexed by
+//! walking the frame records.
+//!
+//! A cache can also be played while it is rendered (`--progressive`). It is written to a 
+//! temporary file until it is complete, and the player only reads the frames the render 
+//! reported as written through `RenderProgress`.
+//!
+//! Caches from before this format are plain text, with an `[] fps=` line and the frames
+//! separated by empty lines. Those can still be read.
+
+use std::path::Path;
+use std::fs::File;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+const MAGIC: &[u8; 4] = b"MOFB";
+const INDEX_MAGIC: &[u8; 4] = b"MOFI";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_SIZE: u64 = 21;
+const RECORD_HEADER_SIZE: u64 = 5;
+const INDEX_ENTRY_SIZE: u64 = 9;
+const INDEX_TRAILER_SIZE: u64 = 16;
+
+const KEY_FRAME: u8 = 0;
+const DELTA_FRAME: u8 = 1;
+const KEYFRAME_INTERVAL: usize = 64;
+// unchanged cells between two changed runs are written as part of one run up to this length,
+// since starting a new run costs about as much
+const RUN_GAP: usize = 4;
+const ZSTD_LEVEL: i32 = 3;
+// decoded frames kept around by the reader. Going backwards through a cache (`--ping-pong`) 
+// decodes everything from the key frame before, so this holds two key frame intervals
+const DECODED_FRAMES: usize = 2 * KEYFRAME_INTERVAL;
+
+/// What every frame of a cache shares
+#[derive(Clone, Copy)]
+pub struct CacheInfo {
+    pub fps: f64,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+impl CacheInfo {
+    fn cells(&self) -> usize {
+        return self.cols as usize * self.rows as usize;
+    }
+}
+
+const RENDERING: u8 = 0;
+const DONE: u8 = 1;
+const FAILED: u8 = 2;
+
+/// How far the render of a cache is, shared with whoever plays or reports on the cache 
+/// while it is rendered
+#[derive(Default)]
+pub struct RenderProgress {
+    frames: AtomicUsize,
+    state: AtomicU8,
+}
+
+impl RenderProgress {
+    /// Frames written to the cache so far
+    pub fn frames(&self) -> usize {
+        return self.frames.load(Ordering::Acquire);
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.state.load(Ordering::Acquire) == DONE;
+    }
+
+    /// Whether the render stopped before the cache was complete
+    pub fn has_failed(&self) -> bool {
+        return self.state.load(Ordering::Acquire) == FAILED;
+    }
+}
+
+/// Where a frame record starts, and whether it is a key frame
+#[derive(Clone, Copy)]
+struct Record {
+    offset: u64,
+    kind: u8,
+}
+
+/// Appends frames to a new cache
+pub struct CacheWriter {
+    file: BufWriter<File>,
+    info: CacheInfo,
+    compressor: zstd::bulk::Compressor<'static>,
+    previous: Vec<u8>,
+    records: Vec<Record>,
+    written_bytes: u64,
+    progress: Option<Arc<RenderProgress>>,
+}
+
+impl CacheWriter {
+    /// Creates the cache at `path` and writes its header
+    pub fn create(path: &Path, info: CacheInfo) -> io::Result<CacheWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[FORMAT_VERSION])?;
+        file.write_all(&info.fps.to_le_bytes())?;
+        file.write_all(&info.cols.to_le_bytes())?;
+        file.write_all(&info.rows.to_le_bytes())?;
+
+        return Ok(CacheWriter {
+            file,
+            info,
+            compressor: zstd::bulk::Compressor::new(ZSTD_LEVEL)?,
+            previous: Vec::new(),
+            records: Vec::new(),
+            written_bytes: HEADER_SIZE,
+            progress: None,
+        });
+    }
+
+    /// Appends a frame of `cols * rows` cells
+    pub fn write_frame(&mut self, cells: &[u8]) -> io::Result<()> {
+        if cells.len() != self.info.cells() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("frame has {} cells, the cache expects {}x{}", cells.len(), self.info.cols, self.info.rows)));
+        }
+
+        let key_frame = self.records.len().is_multiple_of(KEYFRAME_INTERVAL);
+        let mut delta = Vec::new();
+        if !key_frame {
+            encode_delta(&self.previous, cells, &mut delta);
+        }
+        let (kind, payload) =
+            if key_frame || delta.len() >= cells.len() {(KEY_FRAME, cells)}
+            else {(DELTA_FRAME, delta.as_slice())};
+
+        let compressed = self.compressor.compress(payload)?;
+        self.file.write_all(&[kind])?;
+        self.file.write_all(&(compressed.len() as u32).to_le_bytes())?;
+        self.file.write_all(&compressed)?;
+
+        self.previous.clear();
+        self.previous.extend_from_slice(cells);
+        self.records.push(Record { offset: self.written_bytes, kind });
+        self.written_bytes += RECORD_HEADER_SIZE + compressed.len() as u64;
+
+        // the frame has to be in the file before it is reported
+        if let Some(progress) = &self.progress {
+            self.file.flush()?;
+            progress.frames.store(self.records.len(), Ordering::Release);
+        }
+        return Ok(());
+    }
+
+    /// Reports every written frame from now on, so the cache can be played while it is 
+    /// rendered
+    pub fn progress(&mut self) -> io::Result<Arc<RenderProgress>> {
+        self.file.flush()?;
+        let progress = self.progress.get_or_insert_with(|| Arc::new(RenderProgress {
+            frames: AtomicUsize::new(self.records.len()),
+            state: AtomicU8::new(RENDERING),
+        }));
+        return Ok(progress.clone());
+    }
+
+    /// Writes the index and flushes the cache. Returns the number of frames and the size of 
+    /// the cache in bytes.
+    pub fn finish(mut self) -> io::Result<(usize, u64)> {
+        let index_offset = self.written_bytes;
+        for record in self.records.iter() {
+            self.file.write_all(&record.offset.to_le_bytes())?;
+            self.file.write_all(&[record.kind])?;
+        }
+        self.file.write_all(&index_offset.to_le_bytes())?;
+        self.file.write_all(&(self.records.len() as u32).to_le_bytes())?;
+        self.file.write_all(INDEX_MAGIC)?;
+        self.file.flush()?;
+        if let Some(progress) = &self.progress {
+            progress.state.store(DONE, Ordering::Release);
+        }
+
+        let size = index_offset + self.records.len() as u64 * INDEX_ENTRY_SIZE + INDEX_TRAILER_SIZE;
+        return Ok((self.records.len(), size));
+    }
+}
+
+impl Drop for CacheWriter {
+    // a render that panics or is given up on drops its writer before finishing the cache
+    fn drop(&mut self) {
+        if let Some(progress) = &self.progress {
+            progress.state.compare_exchange(RENDERING, FAILED, Ordering::AcqRel, Ordering::Acquire).ok();
+        }
+    }
+}
+
+/// Reads the frames of a cache as they are needed. The most recently decoded frames are 
+/// kept in a ring buffer, older ones are decoded again from the cache.
+pub struct CacheReader {
+    info: CacheInfo,
+    frames: Frames,
+}
+
+enum Frames {
+    Indexed(IndexedFrames),
+    // plain text caches are read whole
+    Text(Vec<Vec<u8>>),
+}
+
+struct IndexedFrames {
+    file: BufReader<File>,
+    records: Vec<Record>,
+    decompressor: zstd::bulk::Decompressor<'static>,
+    // the last decoded frame and its number, which the next delta frame applies to
+    frame: Vec<u8>,
+    frame_number: Option<usize>,
+    decoded: VecDeque<(usize, Vec<u8>)>,
+    // set while the cache is being rendered, with the offset of the next frame record
+    rendering: Option<(Arc<RenderProgress>, u64)>,
+}
+
+impl CacheReader {
+    /// Opens a cache and reads its header and index. Fails with `InvalidData` when the file 
+    /// is not a frame cache or when it is cut off.
+    pub fn open(path: &Path) -> io::Result<CacheReader> {
+        if is_legacy(path)? {
+            let (info, frames) = read_legacy(path)?;
+            return Ok(CacheReader { info, frames: Frames::Text(frames) });
+        }
+
+        let (mut file, info) = open_cache(path)?;
+        let records = match read_index(&mut file)? {
+            Some(records) => records,
+            None => scan_records(&mut file)?,
+        };
+        return Ok(CacheReader { info, frames: Frames::Indexed(IndexedFrames::new(file, records, info, None)?) });
+    }
+
+    /// Opens a cache that is still being rendered. Only the frames `progress` reports as 
+    /// written are read, see `refresh`.
+    pub fn open_rendering(path: &Path, progress: Arc<RenderProgress>) -> io::Result<CacheReader> {
+        let (file, info) = open_cache(path)?;
+        let frames = IndexedFrames::new(file, Vec::new(), info, Some((progress, HEADER_SIZE)))?;
+        let mut reader = CacheReader { info, frames: Frames::Indexed(frames) };
+        reader.refresh()?;
+        return Ok(reader);
+    }
+
+    /// Whether the cache is still being rendered, so more frames may follow
+    pub fn is_rendering(&self) -> bool {
+        return matches!(&self.frames, Frames::Indexed(frames) if frames.rendering.is_some());
+    }
+
+    /// Picks up the frames rendered since the last refresh. Fails if the render failed.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let Frames::Indexed(frames) = &mut self.frames else {
+            return Ok(());
+        };
+        let Some((progress, offset)) = &mut frames.rendering else {
+            return Ok(());
+        };
+        // the state is read first, so the frame count is final when the render is done
+        let done = progress.is_done();
+        if progress.has_failed() {
+            return Err(io::Error::other("the render of the cache failed"));
+        }
+        for _ in frames.records.len()..progress.frames() {
+            let (record, next_offset) = read_record_header(&mut frames.file, *offset)?;
+            frames.records.push(record);
+            *offset = next_offset;
+        }
+        if done {
+            frames.rendering = None;
+        }
+        return Ok(());
+    }
+
+    pub fn info(&self) -> CacheInfo {
+        return self.info;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        return match &self.frames {
+            Frames::Indexed(frames) => frames.records.len(),
+            Frames::Text(frames) => frames.len(),
+        };
+    }
+
+    /// Returns frame `n`, decoding it if it isn't in the ring buffer
+    pub fn frame(&mut self, n: usize) -> io::Result<&[u8]> {
+        let cells = self.info.cells();
+        return match &mut self.frames {
+            Frames::Indexed(frames) => frames.frame(n, cells),
+            Frames::Text(frames) => Ok(&frames[n]),
+        };
+    }
+}
+
+impl IndexedFrames {
+    fn new(file: BufReader<File>, records: Vec<Record>, info: CacheInfo, rendering: Option<(Arc<RenderProgress>, u64)>) -> io::Result<IndexedFrames> {
+        return Ok(IndexedFrames {
+            file,
+            records,
+            decompressor: zstd::bulk::Decompressor::new()?,
+            frame: vec![b' '; info.cells()],
+            frame_number: None,
+            decoded: VecDeque::with_capacity(DECODED_FRAMES),
+            rendering,
+        });
+    }
+
+    fn frame(&mut self, n: usize, cells: usize) -> io::Result<&[u8]> {
+        if let Some(position) = self.decoded.iter().position(|(number, _)| *number == n) {
+            return Ok(&self.decoded[position].1);
+        }
+
+        // decode from the last key frame, or continue from the last decoded frame if it is 
+        // in between
+        let key_frame = (0..=n).rev().find(|i| self.records[*i].kind == KEY_FRAME).unwrap_or(0);
+        let start = match self.frame_number {
+            Some(last) if last >= key_frame && last < n => last + 1,
+            _ => key_frame,
+        };
+        for i in start..=n {
+            self.decode(i, cells)?;
+            if self.decoded.iter().any(|(number, _)| *number == i) {
+                continue;
+            }
+            let mut buffer =
+                if self.decoded.len() == DECODED_FRAMES {self.decoded.pop_front().unwrap().1}
+                else {Vec::with_capacity(cells)};
+            buffer.clear();
+            buffer.extend_from_slice(&self.frame);
+            self.decoded.push_back((i, buffer));
+        }
+        return Ok(&self.decoded.back().unwrap().1);
+    }
+
+    /// Decodes frame `n` into `frame`, which has to hold frame `n - 1` for delta frames
+    fn decode(&mut self, n: usize, cells: usize) -> io::Result<()> {
+        let record = self.records[n];
+        // a frame that fails to decode is left half applied
+        self.frame_number = None;
+        self.file.seek(SeekFrom::Start(record.offset + 1))?;
+        let mut length = [0u8; 4];
+        self.file.read_exact(&mut length)?;
+        let mut compressed = vec![0u8; u32::from_le_bytes(length) as usize];
+        self.file.read_exact(&mut compressed)?;
+
+        // a delta is never written when it is larger than a key frame
+        let payload = self.decompressor.decompress(&compressed, cells)?;
+        match record.kind {
+            KEY_FRAME if payload.len() == self.frame.len() => self.frame.copy_from_slice(&payload),
+            DELTA_FRAME => apply_delta(&mut self.frame, &payload).ok_or_else(|| invalid_data("broken delta frame"))?,
+            _ => return Err(invalid_data("broken frame record")),
+        }
+        self.frame_number = Some(n);
+        return Ok(());
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, String::from(message));
+}
+
+/// Opens a cache of the current format and reads its header
+fn open_cache(path: &Path) -> io::Result<(BufReader<File>, CacheInfo)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.read_exact(&mut header).map_err(|_| invalid_data("not a frame cache"))?;
+    if &header[0..4] != MAGIC {
+        return Err(invalid_data("not a frame cache"));
+    }
+    if header[4] != FORMAT_VERSION {
+        return Err(invalid_data(&format!("unsupported cache format version {}", header[4])));
+    }
+    let info = CacheInfo {
+        fps: f64::from_le_bytes(header[5..13].try_into().unwrap()),
+        cols: u32::from_le_bytes(header[13..17].try_into().unwrap()),
+        rows: u32::from_le_bytes(header[17..21].try_into().unwrap()),
+    };
+    return Ok((file, info));
+}
+
+/// Reads the frame record at `offset`. Returns it and the offset of the next record.
+fn read_record_header(file: &mut BufReader<File>, offset: u64) -> io::Result<(Record, u64)> {
+    let mut record_header = [0u8; RECORD_HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut record_header).map_err(|_| invalid_data("cache is cut off"))?;
+    let length = u32::from_le_bytes(record_header[1..5].try_into().unwrap()) as u64;
+    return Ok((Record { offset, kind: record_header[0] }, offset + RECORD_HEADER_SIZE + length));
+}
+
+/// Reads the index at the end of a cache, or returns `None` if it has none
+fn read_index(file: &mut BufReader<File>) -> io::Result<Option<Vec<Record>>> {
+    let size = file.seek(SeekFrom::End(0))?;
+    if size < HEADER_SIZE + INDEX_TRAILER_SIZE {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; INDEX_TRAILER_SIZE as usize];
+    file.seek(SeekFrom::End(-(INDEX_TRAILER_SIZE as i64)))?;
+    file.read_exact(&mut trailer)?;
+    if &trailer[12..16] != INDEX_MAGIC {
+        return Ok(None);
+    }
+    let index_offset = u64::from_le_bytes(trailer[0..8].try_into().unwrap());
+    let frames = u32::from_le_bytes(trailer[8..12].try_into().unwrap()) as u64;
+    if index_offset < HEADER_SIZE || index_offset + frames * INDEX_ENTRY_SIZE + INDEX_TRAILER_SIZE != size {
+        return Err(invalid_data("broken cache index"));
+    }
+
+    let mut index = vec![0u8; (frames * INDEX_ENTRY_SIZE) as usize];
+    file.seek(SeekFrom::Start(index_offset))?;
+    file.read_exact(&mut index)?;
+    let records = index.chunks(INDEX_ENTRY_SIZE as usize).map(|entry| Record {
+        offset: u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+        kind: entry[8],
+    }).collect();
+    return Ok(Some(records));
+}
+
+/// Indexes a cache by walking its frame records
+fn scan_records(file: &mut BufReader<File>) -> io::Result<Vec<Record>> {
+    let size = file.seek(SeekFrom::End(0))?;
+    let mut records = Vec::new();
+    let mut offset = HEADER_SIZE;
+    while offset < size {
+        let (record, next_offset) = read_record_header(file, offset)?;
+        records.push(record);
+        offset = next_offset;
+    }
+    if offset != size {
+        return Err(invalid_data("cache is cut off"));
+    }
+    return Ok(records);
+}
+
+fn is_legacy(path: &Path) -> io::Result<bool> {
+    let mut start = [0u8; 7];
+    let read = File::open(path)?.read(&mut start)?;
+    return Ok(&start[..read] == b"[] fps=");
+}
+
+/// Reads a plain text cache. Its rows can differ in length, so they are padded with blank
+/// cells to the widest row.
+fn read_legacy(path: &Path) -> io::Result<(CacheInfo, Vec<Vec<u8>>)> {
+    let file_string = std::fs::read_to_string(path)?;
+    let fps = file_string.lines().next()
+        .and_then(|line| line.strip_prefix("[] fps="))
+        .and_then(|fps| fps.trim().parse::<f64>().ok())
+        .ok_or_else(|| invalid_data("broken fps line"))?;
+
+    let mut text_frames: Vec<Vec<&str>> = Vec::new();
+    let mut frame_lines: Vec<&str> = Vec::new();
+    for line in file_string.lines().skip(1) {
+        // if line is a frame separator
+        if line.len() <= 1 {
+            if !frame_lines.is_empty() {
+                text_frames.push(std::mem::take(&mut frame_lines));
+            }
+        }
+        else {
+            frame_lines.push(line);
+        }
+    }
+    if !frame_lines.is_empty() {
+        text_frames.push(frame_lines);
+    }
+
+    let cols = text_frames.iter().flatten().map(|line| line.len()).max().unwrap_or(0);
+    let rows = text_frames.first().map(|lines| lines.len()).unwrap_or(0);
+    let frames = text_frames.iter().map(|lines| {
+        let mut cells = vec![b' '; cols * rows];
+        for (row, line) in lines.iter().take(rows).enumerate() {
+            cells[row * cols..row * cols + line.len()].copy_from_slice(line.as_bytes());
+        }
+        cells
+    }).collect();
+
+    return Ok((CacheInfo { fps, cols: cols as u32, rows: rows as u32 }, frames));
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Writes the runs of cells that differ between `previous` and `cells`
+fn encode_delta(previous: &[u8], cells: &[u8], out: &mut Vec<u8>) {
+    let mut last_end = 0;
+    let mut i = 0;
+    while i < cells.len() {
+        if cells[i] == previous[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        let mut j = end;
+        while j < cells.len() && j - end < RUN_GAP {
+            if cells[j] != previous[j] {
+                end = j + 1;
+            }
+            j += 1;
+        }
+        write_varint(out, start - last_end);
+        write_varint(out, end - start);
+        out.extend_from_slice(&cells[start..end]);
+        last_end = end;
+        i = end;
+    }
+}
+
+/// Applies the runs of a delta frame. Returns `None` if a run does not fit in the frame.
+fn apply_delta(frame: &mut [u8], delta: &[u8]) -> Option<()> {
+    let mut pos = 0;
+    let mut cell = 0;
+    while pos < delta.len() {
+        cell += read_varint(delta, &mut pos)?;
+        let length = read_varint(delta, &mut pos)?;
+        let run = delta.get(pos..pos.checked_add(length)?)?;
+        frame.get_mut(cell..cell.checked_add(length)?)?.copy_from_slice(run);
+        pos += length;
+        cell += length;
+    }
+    return Some(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A file in the temp directory that is removed again when the test is done
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            return TempFile(std::env::temp_dir().join(format!("mofetch-test-{}-{}", std::process::id(), name)));
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    const INFO: CacheInfo = CacheInfo { fps: 24.0, cols: 8, rows: 4 };
+
+    /// Frames that mostly change a few cells, with a completely different frame now and 
+    /// then, so the cache has both delta frames and key frames between the intervals
+    fn test_frames(count: usize) -> Vec<Vec<u8>> {
+        let mut frame = vec![b' '; INFO.cells()];
+        return (0..count).map(|i| {
+            if i % 50 == 25 {
+                frame = (0..INFO.cells()).map(|cell| b'a' + ((cell * 7 + i) % 26) as u8).collect();
+            }
+            else {
+                frame[i % INFO.cells()] = b'0' + (i % 10) as u8;
+            }
+            frame.clone()
+        }).collect();
+    }
+
+    fn write_cache(path: &Path, frames: &[Vec<u8>]) -> (usize, u64) {
+        let mut writer = CacheWriter::create(path, INFO).unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        return writer.finish().unwrap();
+    }
+
+    fn records(reader: &CacheReader) -> &[Record] {
+        let Frames::Indexed(frames) = &reader.frames else {
+            panic!("not an indexed cache");
+        };
+        return &frames.records;
+    }
+
+    #[test]
+    fn round_trip() {
+        let file = TempFile::new("round_trip.afb");
+        let frames = test_frames(2 * KEYFRAME_INTERVAL + 10);
+        let (count, size) = write_cache(&file.0, &frames);
+        assert_eq!(count, frames.len());
+        assert_eq!(size, std::fs::metadata(&file.0).unwrap().len());
+
+        let mut reader = CacheReader::open(&file.0).unwrap();
+        assert_eq!(reader.frame_count(), frames.len());
+        assert_eq!(reader.info().fps, INFO.fps);
+        assert_eq!((reader.info().cols, reader.info().rows), (INFO.cols, INFO.rows));
+        for (n, frame) in frames.iter().enumerate() {
+            assert_eq!(reader.frame(n).unwrap(), frame.as_slice(), "frame {}", n);
+        }
+    }
+
+    #[test]
+    fn key_frames() {
+        let file = TempFile::new("key_frames.afb");
+        let frames = test_frames(2 * KEYFRAME_INTERVAL + 10);
+        write_cache(&file.0, &frames);
+
+        let reader = CacheReader::open(&file.0).unwrap();
+        for (n, record) in records(&reader).iter().enumerate() {
+            // every interval starts with a key frame, and frames that change completely 
+            // are written whole as well
+            let expected = if n % KEYFRAME_INTERVAL == 0 || n % 50 == 25 {KEY_FRAME} else {DELTA_FRAME};
+            assert_eq!(record.kind, expected, "frame {}", n);
+        }
+    }
+
+    #[test]
+    fn backwards_access() {
+        let file = TempFile::new("backwards.afb");
+        let frames = test_frames(3 * KEYFRAME_INTERVAL + 5);
+        write_cache(&file.0, &frames);
+
+        // ping-pong plays the cache forwards and then backwards past the decoded frames
+        let mut reader = CacheReader::open(&file.0).unwrap();
+        let order = (0..frames.len()).chain((0..frames.len() - 1).rev());
+        for n in order {
+            assert_eq!(reader.frame(n).unwrap(), frames[n].as_slice(), "frame {}", n);
+        }
+        for n in [KEYFRAME_INTERVAL, KEYFRAME_INTERVAL - 1, 0, 2 * KEYFRAME_INTERVAL + 1, 1] {
+            assert_eq!(reader.frame(n).unwrap(), frames[n].as_slice(), "frame {}", n);
+        }
+    }
+
+    #[test]
+    fn wrong_frame_size() {
+        let file = TempFile::new("wrong_size.afb");
+        let mut writer = CacheWriter::create(&file.0, INFO).unwrap();
+        let error = writer.write_frame(&[b' '; 3]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn without_index() {
+        let file = TempFile::new("without_index.afb");
+        let frames = test_frames(KEYFRAME_INTERVAL + 3);
+        // a writer that is dropped before it is finished leaves the frames without an index
+        {
+            let mut writer = CacheWriter::create(&file.0, INFO).unwrap();
+            for frame in frames.iter() {
+                writer.write_frame(frame).unwrap();
+            }
+        }
+        let mut reader = CacheReader::open(&file.0).unwrap();
+        assert_eq!(reader.frame_count(), frames.len());
+        assert_eq!(reader.frame(frames.len() - 1).unwrap(), frames.last().unwrap().as_slice());
+    }
+
+    #[test]
+    fn truncated() {
+        let file = TempFile::new("truncated.afb");
+        write_cache(&file.0, &test_frames(10));
+        let bytes = std::fs::read(&file.0).unwrap();
+
+        // cut into the last frame record: the index is gone and the records don't add up
+        let frames_end = bytes.len() - 10 * INDEX_ENTRY_SIZE as usize - INDEX_TRAILER_SIZE as usize;
+        std::fs::write(&file.0, &bytes[..frames_end - 2]).unwrap();
+        let error = CacheReader::open(&file.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // cut into the header
+        std::fs::write(&file.0, &bytes[..10]).unwrap();
+        let error = CacheReader::open(&file.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // a different format version
+        let mut other_version = bytes.clone();
+        other_version[4] = FORMAT_VERSION + 1;
+        std::fs::write(&file.0, &other_version).unwrap();
+        assert!(CacheReader::open(&file.0).is_err());
+    }
+
+    #[test]
+    fn rendering() {
+        let file = TempFile::new("rendering.afb");
+        let frames = test_frames(20);
+        let mut writer = CacheWriter::create(&file.0, INFO).unwrap();
+        let progress = writer.progress().unwrap();
+        let mut reader = CacheReader::open_rendering(&file.0, progress.clone()).unwrap();
+        assert_eq!(reader.frame_count(), 0);
+
+        for frame in frames[..12].iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        reader.refresh().unwrap();
+        assert_eq!(reader.frame_count(), 12);
+        assert!(reader.is_rendering());
+        assert_eq!(reader.frame(11).unwrap(), frames[11].as_slice());
+
+        for frame in frames[12..].iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap();
+        reader.refresh().unwrap();
+        assert!(!reader.is_rendering());
+        assert_eq!(reader.frame_count(), frames.len());
+        assert_eq!(reader.frame(19).unwrap(), frames[19].as_slice());
+    }
+
+    #[test]
+    fn failed_render() {
+        let file = TempFile::new("failed_render.afb");
+        let mut writer = CacheWriter::create(&file.0, INFO).unwrap();
+        let progress = writer.progress().unwrap();
+        let mut reader = CacheReader::open_rendering(&file.0, progress.clone()).unwrap();
+        drop(writer);
+        assert!(progress.has_failed());
+        assert!(reader.refresh().is_err());
+    }
+
+    #[test]
+    fn legacy() {
+        let file = TempFile::new("legacy.afb");
+        std::fs::write(&file.0, "[] fps=12.5\nab\nabcd\n\nxy\nzz\n\n").unwrap();
+
+        let mut reader = CacheReader::open(&file.0).unwrap();
+        assert_eq!(reader.info().fps, 12.5);
+        assert_eq!((reader.info().cols, reader.info().rows), (4, 2));
+        assert_eq!(reader.frame_count(), 2);
+        // shorter rows are padded to the widest row
+        assert_eq!(reader.frame(0).unwrap(), b"ab  abcd");
+        assert_eq!(reader.frame(1).unwrap(), b"xy  zz  ");
+    }
+
+    #[test]
+    fn delta() {
+        let previous = b"aaaaaaaaaaaaaaaaaaaa".to_vec();
+        let cases: [&[u8; 20]; 5] = [
+            b"aaaaaaaaaaaaaaaaaaaa", // unchanged
+            b"baaaaaaaaaaaaaaaaaab", // first and last cell
+            b"abababaaaaaaaaaaaaaa", // changes within the run gap are one run
+            b"abaaaaaaaaaaaaaaaaba", // changes further apart are separate runs
+            b"bbbbbbbbbbbbbbbbbbbb", // everything
+        ];
+        for cells in cases {
+            let mut delta = Vec::new();
+            encode_delta(&previous, cells, &mut delta);
+            let mut frame = previous.clone();
+            apply_delta(&mut frame, &delta).unwrap();
+            assert_eq!(frame, cells, "delta {:?}", delta);
+        }
+
+        let mut delta = Vec::new();
+        encode_delta(&previous, cases[0], &mut delta);
+        assert!(delta.is_empty());
+
+        let mut delta = Vec::new();
+        encode_delta(&previous, cases[2], &mut delta);
+        assert_eq!(delta, [1, 5, b'b', b'a', b'b', b'a', b'b']);
+
+        let mut delta = Vec::new();
+        encode_delta(&previous, cases[3], &mut delta);
+        assert_eq!(delta, [1, 1, b'b', 16, 1, b'b']);
+    }
+
+    #[test]
+    fn broken_delta() {
+        let mut frame = vec![b' '; 4];
+        // a run past the end of the frame
+        assert!(apply_delta(&mut frame, &[2, 3, b'a', b'b', b'c']).is_none());
+        // a run longer than the delta
+        assert!(apply_delta(&mut frame, &[0, 3, b'a']).is_none());
+        // a count that is cut off
+        assert!(apply_delta(&mut frame, &[0x80]).is_none());
+    }
+
+    #[test]
+    fn varint() {
+        for value in [0, 1, 127, 128, 300, 16383, 16384, u32::MAX as usize, usize::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let mut pos = 0;
+            assert_eq!(read_varint(&out, &mut pos), Some(value));
+            assert_eq!(pos, out.len());
+        }
+
+        let mut out = Vec::new();
+        write_varint(&mut out, 127);
+        assert_eq!(out, [0x7f]);
+        out.clear();
+        write_varint(&mut out, 128);
+        assert_eq!(out, [0x80, 0x01]);
+
+        // cut off, and longer than any usize
+        assert_eq!(read_varint(&[0x80, 0x80], &mut 0), None);
+        let mut too_long = vec![0x80; 10];
+        too_long.push(0x01);
+        assert_eq!(read_varint(&too_long, &mut 0), None);
+    }
+}
@@ -3,6 +3,8 @@ pub struct HelpOption {
     pub long: Option<String>,
     pub desc: Option<String>,
     pub datatype: Option<String>,
+    /// accepted values, offered by the shell completions
+    pub values: Option<Vec<String>>,
 }
 
 pub struct OptionGroup {
@@ -21,43 +23,30 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("help".into()),
         desc: Some("Display this help message".into()),
         datatype: None,
+        values: None,
     };
     information_options.push(help);
 
     // version
     let version = HelpOption {
-        short: Some("v".into()),
+        short: Some("V".into()),
         long: Some("version".into()),
         desc: Some("Show mofetch version".into()),
         datatype: None,
+        values: None,
     };
     information_options.push(version);
 
     // verbose
     let verbose = HelpOption {
-        short: Some("V".into()),
+        short: Some("v".into()),
         long: Some("verbose".into()),
         desc: Some("Show process information".into()),
         datatype: None,
+        values: None,
     };
     information_options.push(verbose);
 
-    let sysinfo = HelpOption {
-        short: Some("I".into()),
-        long: Some("hide-info".into()),
-        desc: Some("Do not show system info, and only render the thumbnail".into()),
-        datatype: None,
-    };
-    information_options.push(sysinfo);
-
-    // adapters
-    let gpus = HelpOption {
-        short: None,
-        long: Some("gpus".into()),
-        desc: Some("Get all available GPU adapters by index".into()),
-        datatype: None,
-    };
-    information_options.push(gpus);
 
     let information_vec = OptionGroup {
         name: Some("Information options".into()),
@@ -68,12 +57,53 @@ pub fn init_options() -> Vec<OptionGroup> {
 
     // pre-processing options
     let mut pre_processing_options: Vec<HelpOption> = Vec::new();
+    // input
+    let input = HelpOption {
+        short: Some("i".into()),
+        long: Some("input".into()),
+        desc: Some("Input file to render. Can also be given as the last argument".into()),
+        datatype: Some("path".into()),
+        values: None,
+    };
+    pre_processing_options.push(input);
+
+    // input dir
+    let input_dir = HelpOption {
+        short: None,
+        long: Some("input-dir".into()),
+        desc: Some("Directory that --random picks the input from".into()),
+        datatype: Some("path".into()),
+        values: None,
+    };
+    pre_processing_options.push(input_dir);
+
+    // random
+    let random = HelpOption {
+        short: Some("r".into()),
+        long: Some("random".into()),
+        desc: Some("Play a random media file from the input directory, unless an input is given".into()),
+        datatype: None,
+        values: None,
+    };
+    pre_processing_options.push(random);
+
+    // profile
+    let profile = HelpOption {
+        short: Some("p".into()),
+        long: Some("profile".into()),
+        desc: Some("Use the settings of [profiles.<name>] in the config. Without it, the profile is picked by the [inputs] of the config".into()),
+        datatype: Some("name".into()),
+        values: None,
+    };
+    pre_processing_options.push(profile);
+
     // overwrite cache
     let overwrite_cache = HelpOption {
         short: Some("o".into()),
         long: Some("overwrite-cache".into()),
         desc: Some("Ignore and overwrite existing cache. Useful if the cache is corrupt".into()),
         datatype: None,
+        values: None,
     };
     pre_processing_options.push(overwrite_cache);
 
@@ -83,6 +113,7 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("max-width".into()),
         desc: Some("Set max width of source image to (0..1) % of terminal width".into()),
         datatype: Some("float".into()),
+        values: None,
     };
     pre_processing_options.push(max_width);
 
@@ -92,15 +123,57 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("max-height".into()),
         desc: Some("Set max height of source image to (0..1) % of terminal height".into()),
         datatype: Some("float".into()),
+        values: None,
     };
     pre_processing_options.push(max_height);
 
+    // fit
+    let fit = HelpOption {
+        short: None,
+        long: Some("fit".into()),
+        desc: Some("Fit the source into the thumbnail: contain (keep all of it), cover (fill the thumbnail and cut off the rest) or stretch".into()),
+        datatype: Some("mode".into()),
+        values: Some(vec!["contain".into(), "cover".into(), "stretch".into()]),
+    };
+    pre_processing_options.push(fit);
+
+    // crop
+    let crop = HelpOption {
+        short: None,
+        long: Some("crop".into()),
+        desc: Some("Crop the source before fitting it, to x:y:w:h in source pixels or center-square".into()),
+        datatype: Some("region".into()),
+        values: None,
+    };
+    pre_processing_options.push(crop);
+
+    // target cols
+    let target_cols = HelpOption {
+        short: None,
+        long: Some("target-cols".into()),
+        desc: Some("Set the exact width of the thumbnail in characters. Overrides --max-width".into()),
+        datatype: Some("int".into()),
+        values: None,
+    };
+    pre_processing_options.push(target_cols);
+
+    // target rows
+    let target_rows = HelpOption {
+        short: None,
+        long: Some("target-rows".into()),
+        desc: Some("Set the exact height of the thumbnail in characters. Overrides --max-height".into()),
+        datatype: Some("int".into()),
+        values: None,
+    };
+    pre_processing_options.push(target_rows);
+
     // fps
     let fps = HelpOption {
         short: Some("f".into()),
         long: Some("fps".into()),
         desc: Some("Set the frames per second (capped to source fps)".into()),
         datatype: Some("int".into()),
+        values: None,
     };
     pre_processing_options.push(fps);
 
@@ -110,6 +183,7 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("brightness".into()),
         desc: Some("Set the brightness of the input".into()),
         datatype: Some("float".into()),
+        values: None,
     };
     pre_processing_options.push(brightness);
 
@@ -119,9 +193,90 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("contrast".into()),
         desc: Some("Set the contrast of the input".into()),
         datatype: Some("float".into()),
+        values: None,
     };
     pre_processing_options.push(contrast);
 
+    // gamma
+    let gamma = HelpOption {
+        short: None,
+        long: Some("gamma".into()),
+        desc: Some("Set the gamma of the input. Values above 1 brighten the midtones".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    pre_processing_options.push(gamma);
+
+    // saturation
+    let saturation = HelpOption {
+        short: None,
+        long: Some("saturation".into()),
+        desc: Some("Set the saturation of the input (0 for grayscale)".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    pre_processing_options.push(saturation);
+
+    // invert
+    let invert = HelpOption {
+        short: None,
+        long: Some("invert".into()),
+        desc: Some("Invert the colors of the input".into()),
+        datatype: None,
+        values: None,
+    };
+    pre_processing_options.push(invert);
+
+    // equalize
+    let equalize = HelpOption {
+        short: None,
+        long: Some("equalize".into()),
+        desc: Some("Equalize the histogram of the input: none, global, or clahe (adaptive, per region)".into()),
+        datatype: Some("mode".into()),
+        values: Some(vec!["none".into(), "global".into(), "clahe".into()]),
+    };
+    pre_processing_options.push(equalize);
+
+    // sharpen
+    let sharpen = HelpOption {
+        short: None,
+        long: Some("sharpen".into()),
+        desc: Some("Sharpen the input by this amount (0 to disable)".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    pre_processing_options.push(sharpen);
+
+    // background
+    let background = HelpOption {
+        short: None,
+        long: Some("background".into()),
+        desc: Some("Composite transparent input over a color (black, white, #rrggbb), or leave it blank with transparent".into()),
+        datatype: Some("color".into()),
+        values: Some(vec!["transparent".into(), "black".into(), "white".into()]),
+    };
+    pre_processing_options.push(background);
+
+    // start
+    let start = HelpOption {
+        short: None,
+        long: Some("start".into()),
+        desc: Some("Start the source at this timestamp (e.g. 12.5 or 1:30)".into()),
+        datatype: Some("time".into()),
+        values: None,
+    };
+    pre_processing_options.push(start);
+
+    // end
+    let end = HelpOption {
+        short: None,
+        long: Some("end".into()),
+        desc: Some("End the source at this timestamp".into()),
+        datatype: Some("time".into()),
+        values: None,
+    };
+    pre_processing_options.push(end);
+
     let pre_processing_vec = OptionGroup {
         name: Some("Pre-processing options".into()),
         options: pre_processing_options,
@@ -135,8 +290,9 @@ pub fn init_options() -> Vec<OptionGroup> {
     let adapter_index = HelpOption {
         short: Some("a".into()),
         long: Some("adapter-index".into()),
-        desc: Some("Use GPU adapter with the provided index to specify GPU device. Use --gpus to get available adapters".into()),
+        desc: Some("Use GPU adapter with the provided index to specify GPU device. Use \"mofetch gpus\" to get available adapters".into()),
         datatype: Some("int".into()),
+        values: None,
     };
     shader_options.push(adapter_index);
 
@@ -146,6 +302,7 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("no-edges".into()),
         desc: Some("Don't draw ASCII edge lines (/ \\ _ |)".into()),
         datatype: None,
+        values: None,
     };
     shader_options.push(no_edges);
 
@@ -155,14 +312,489 @@ pub fn init_options() -> Vec<OptionGroup> {
         long: Some("edge-threshold".into()),
         desc: Some("Set the required percentage of edge strength in each tile for a tile to be rendered as an edge (0..1). Increasing this value can be useful if stronger edgelines are desired, while decreasing it can reduce the noise created by too many edges from the input.".into()),
         datatype: Some("float".into()),
+        values: None,
     };
     shader_options.push(edge_threshold);
 
+    // dither
+    let dither = HelpOption {
+        short: None,
+        long: Some("dither".into()),
+        desc: Some("Dither the luminance ramp to reduce banding: none, bayer, floyd-steinberg or atkinson".into()),
+        datatype: Some("mode".into()),
+        values: Some(vec!["none".into(), "bayer".into(), "floyd-steinberg".into(), "atkinson".into()]),
+    };
+    shader_options.push(dither);
+
+    // charset
+    let charset = HelpOption {
+        short: None,
+        long: Some("charset".into()),
+        desc: Some("Set the glyphs of the luminance ramp, from the darkest to the brightest (e.g. \" .:-=+*#%@\"). Up to 64 printable ASCII characters".into()),
+        datatype: Some("chars".into()),
+        values: None,
+    };
+    shader_options.push(charset);
+
+    // shape glyphs
+    let shape_glyphs = HelpOption {
+        short: None,
+        long: Some("shape-glyphs".into()),
+        desc: Some("Pick glyphs by matching the shape in each tile against a larger charset (| / \\ _ - = ^ v ( ) < > o O): the edges of edge tiles, and strokes of light in the other tiles".into()),
+        datatype: None,
+        values: None,
+    };
+    shader_options.push(shape_glyphs);
+
+    // temporal
+    let temporal = HelpOption {
+        short: None,
+        long: Some("temporal".into()),
+        desc: Some("Reduce flicker in videos: none, hysteresis (only change a glyph when the source changes enough) or ema (smooth the luminance over time)".into()),
+        datatype: Some("mode".into()),
+        values: Some(vec!["none".into(), "hysteresis".into(), "ema".into()]),
+    };
+    shader_options.push(temporal);
+
+    // temporal strength
+    let temporal_strength = HelpOption {
+        short: None,
+        long: Some("temporal-strength".into()),
+        desc: Some("Set the strength of --temporal. For hysteresis, the extra margin in luminance steps; for ema, the weight of the last frame (0..1)".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(temporal_strength);
+
+    // sigma
+    let sigma = HelpOption {
+        short: None,
+        long: Some("sigma".into()),
+        desc: Some("Set the blur radius used for edge detection. Larger values ignore finer details".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(sigma);
+
+    // sigma ratio
+    let sigma_ratio = HelpOption {
+        short: None,
+        long: Some("sigma-ratio".into()),
+        desc: Some("Set how much stronger the subtracted blur is than --sigma".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(sigma_ratio);
+
+    // kernel radius
+    let kernel_radius = HelpOption {
+        short: None,
+        long: Some("kernel-radius".into()),
+        desc: Some("Set the radius in pixels of the blur kernels, up to 32. By default the blurs reach 3x their sigma, up to 32".into()),
+        datatype: Some("int".into()),
+        values: None,
+    };
+    shader_options.push(kernel_radius);
+
+    // dog threshold
+    let dog_threshold = HelpOption {
+        short: None,
+        long: Some("dog-threshold".into()),
+        desc: Some("Set the difference of the blurs required for a pixel to count as an edge".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(dog_threshold);
+
+    // tau
+    let tau = HelpOption {
+        short: None,
+        long: Some("tau".into()),
+        desc: Some("Set the weight of the subtracted blur (extended DoG). Values below 1 give thicker edges".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(tau);
+
+    // phi
+    let phi = HelpOption {
+        short: None,
+        long: Some("phi".into()),
+        desc: Some("Soften the edge threshold; edges fade out below --dog-threshold instead of being cut off. 0 disables it".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(phi);
+
+    // edge bin width
+    let edge_bin_width = HelpOption {
+        short: None,
+        long: Some("edge-bin-width".into()),
+        desc: Some("Set the share of edge directions drawn as | and _ (0..0.125). The rest are drawn as / and \\".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    shader_options.push(edge_bin_width);
+
     let shader_vec = OptionGroup {
         name: Some("Shader options".into()),
         options: shader_options,
     };
     options.push(shader_vec);
 
+
+    // playback options
+    let mut playback_options: Vec<HelpOption> = Vec::new();
+    // hide info
+    let sysinfo = HelpOption {
+        short: Some("I".into()),
+        long: Some("hide-info".into()),
+        desc: Some("Do not show system info, and only render the thumbnail".into()),
+        datatype: None,
+        values: None,
+    };
+    playback_options.push(sysinfo);
+
+    // loops
+    let loops = HelpOption {
+        short: Some("l".into()),
+        long: Some("loops".into()),
+        desc: Some("Play the thumbnail this many times, then exit with the last frame on screen".into()),
+        datatype: Some("int".into()),
+        values: None,
+    };
+    playback_options.push(loops);
+
+    // duration
+    let duration = HelpOption {
+        short: Some("d".into()),
+        long: Some("duration".into()),
+        desc: Some("Exit after this many seconds, leaving the current frame on screen".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    playback_options.push(duration);
+
+    // speed
+    let speed = HelpOption {
+        short: Some("s".into()),
+        long: Some("speed".into()),
+        desc: Some("Set the playback speed (e.g. 0.5 for half speed)".into()),
+        datatype: Some("float".into()),
+        values: None,
+    };
+    playback_options.push(speed);
+
+    // ping pong
+    let ping_pong = HelpOption {
+        short: None,
+        long: Some("ping-pong".into()),
+        desc: Some("Play the thumbnail forwards, then backwards".into()),
+        datatype: None,
+        values: None,
+    };
+    playback_options.push(ping_pong);
+
+    // reprocess on resize
+    let reprocess_on_resize = HelpOption {
+        short: None,
+        long: Some("reprocess-on-resize".into()),
+        desc: Some("Re-render the thumbnail in the background when the terminal is resized. Renders are cached per terminal size".into()),
+        datatype: None,
+        values: None,
+    };
+    playback_options.push(reprocess_on_resize);
+
+    // progressive
+    let progressive = HelpOption {
+        short: None,
+        long: Some("progressive".into()),
+        desc: Some("Start playing while the thumbnail is still being rendered, waiting for frames that aren't rendered yet".into()),
+        datatype: None,
+        values: None,
+    };
+    playback_options.push(progressive);
+
+    // progressive frames
+    let progressive_frames = HelpOption {
+        short: None,
+        long: Some("progressive-frames".into()),
+        desc: Some("Frames to render before playback starts with --progressive".into()),
+        datatype: Some("int".into()),
+        values: None,
+    };
+    playback_options.push(progressive_frames);
+
+    let playback_vec = OptionGroup {
+        name: Some("Playback options".into()),
+        options: playback_options,
+    };
+    options.push(playback_vec);
+
+
+    // debug options
+    let mut debug_options: Vec<HelpOption> = Vec::new();
+    // dump stages
+    let dump_stages = HelpOption {
+        short: None,
+        long: Some("dump-stages".into()),
+        desc: Some("Save the intermediate shader stages of the processed frames as PNGs in this directory: the adjusted source, the DoG mask, the Sobel directions and the ASCII tiles".into()),
+        datatype: Some("dir".into()),
+        values: None,
+    };
+    debug_options.push(dump_stages);
+
+    // dump frames
+    let dump_frames = HelpOption {
+        short: None,
+        long: Some("dump-frames".into()),
+        desc: Some("Comma-separated frame numbers to dump with --dump-stages, starting at 1 (default: 1)".into()),
+        datatype: Some("list".into()),
+        values: None,
+    };
+    debug_options.push(dump_frames);
+
+    let debug_vec = OptionGroup {
+        name: Some("Debug options".into()),
+        options: debug_options,
+    };
+    options.push(debug_vec);
+
+
+    // cache options
+    let mut cache_options: Vec<HelpOption> = Vec::new();
+    // max size
+    let max_size = HelpOption {
+        short: None,
+        long: Some("max-size".into()),
+        desc: Some("Remove the least recently used caches until all caches fit in this size (e.g. 500MiB or 2GiB)".into()),
+        datatype: Some("size".into()),
+        values: None,
+    };
+    cache_options.push(max_size);
+
+    // older than
+    let older_than = HelpOption {
+        short: None,
+        long: Some("older-than".into()),
+        desc: Some("Remove the caches that were not used for this long (e.g. 12h, 30d or 2w)".into()),
+        datatype: Some("age".into()),
+        values: None,
+    };
+    cache_options.push(older_than);
+
+    let cache_vec = OptionGroup {
+        name: Some("Cache options".into()),
+        options: cache_options,
+    };
+    options.push(cache_vec);
+
+
+    // path options
+    let mut path_options: Vec<HelpOption> = Vec::new();
+    // config
+    let config = HelpOption {
+        short: None,
+        long: Some("config".into()),
+        desc: Some("Use this config file instead of the one in the user config folder (or $MOFETCH_CONFIG)".into()),
+        datatype: Some("path".into()),
+        values: None,
+    };
+    path_options.push(config);
+
+    // cache dir
+    let cache_dir = HelpOption {
+        short: None,
+        long: Some("cache-dir".into()),
+        desc: Some("Keep the caches in this directory instead of the user cache folder (or $MOFETCH_CACHE_DIR)".into()),
+        datatype: Some("path".into()),
+        values: None,
+    };
+    path_options.push(cache_dir);
+
+    // data dir
+    let data_dir = HelpOption {
+        short: None,
+        long: Some("data-dir".into()),
+        desc: Some("Extract the frames of a render into this directory instead of the user data folder (or $MOFETCH_DATA_DIR)".into()),
+        datatype: Some("path".into()),
+        values: None,
+    };
+    path_options.push(data_dir);
+
+    let path_vec = OptionGroup {
+        name: Some("Path options".into()),
+        options: path_options,
+    };
+    options.push(path_vec);
+
     return options;
-}
\ No newline at end of file
+}
+pub struct CommandHelp {
+    pub name: Option<String>,
+    pub args: Option<String>,
+    pub desc: Option<String>,
+    /// names of the option groups the command accepts
+    pub groups: Vec<String>,
+    pub subcommands: Vec<CommandHelp>,
+}
+
+pub fn init_commands() -> Vec<CommandHelp> {
+    let mut commands = Vec::new();
+
+    // show
+    let show = CommandHelp {
+        name: Some("show".into()),
+        args: Some("[input]".into()),
+        desc: Some("Play the thumbnail next to the system info, rendering it first if it isn't cached (default)".into()),
+        groups: vec!["Information options".into(), "Pre-processing options".into(), "Shader options".into(),
+            "Playback options".into(), "Debug options".into(), "Path options".into()],
+        subcommands: Vec::new(),
+    };
+    commands.push(show);
+
+    // render
+    let render = CommandHelp {
+        name: Some("render".into()),
+        args: Some("<input>".into()),
+        desc: Some("Render the input into the cache without playing it".into()),
+        groups: vec!["Information options".into(), "Pre-processing options".into(), "Shader options".into(),
+            "Debug options".into(), "Path options".into()],
+        subcommands: Vec::new(),
+    };
+    commands.push(render);
+
+    // cache
+    let mut cache_subcommands = Vec::new();
+    let cache_ls = CommandHelp {
+        name: Some("ls".into()),
+        args: None,
+        desc: Some("List the cached thumbnails with their settings, size and when they were last used".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_ls);
+
+    let cache_rm = CommandHelp {
+        name: Some("rm".into()),
+        args: Some("<input>".into()),
+        desc: Some("Remove every cache of an input, including its size variants".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_rm);
+
+    let cache_clear = CommandHelp {
+        name: Some("clear".into()),
+        args: None,
+        desc: Some("Remove all caches".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_clear);
+
+    let cache_info = CommandHelp {
+        name: Some("info".into()),
+        args: Some("<input>".into()),
+        desc: Some("Show the frame size, frame count, fps and file size of the caches of an input".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_info);
+
+    let cache_prune = CommandHelp {
+        name: Some("prune".into()),
+        args: None,
+        desc: Some("Remove old or least recently used caches with --older-than and --max-size. Without either, the caches are evicted down to cache_max_size from the config".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_prune);
+
+    let cache_warm = CommandHelp {
+        name: Some("warm".into()),
+        args: Some("<dir>".into()),
+        desc: Some("Render every media file in the directory that isn't cached yet, so --random never has to wait for a render".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    cache_subcommands.push(cache_warm);
+
+    let cache = CommandHelp {
+        name: Some("cache".into()),
+        args: Some("<command>".into()),
+        desc: Some("Manage the rendered thumbnails in the cache directory".into()),
+        groups: vec!["Cache options".into(), "Path options".into()],
+        subcommands: cache_subcommands,
+    };
+    commands.push(cache);
+
+    // gpus
+    let gpus = CommandHelp {
+        name: Some("gpus".into()),
+        args: None,
+        desc: Some("List all available GPU adapters by index".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    commands.push(gpus);
+
+    // config
+    let mut config_subcommands = Vec::new();
+    let config_init = CommandHelp {
+        name: Some("init".into()),
+        args: None,
+        desc: Some("Write the default config file, if there is none yet".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    config_subcommands.push(config_init);
+
+    let config_path = CommandHelp {
+        name: Some("path".into()),
+        args: None,
+        desc: Some("Print the path of the config file".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    config_subcommands.push(config_path);
+
+    let config_edit = CommandHelp {
+        name: Some("edit".into()),
+        args: None,
+        desc: Some("Open the config file in $VISUAL or $EDITOR".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    config_subcommands.push(config_edit);
+
+    let config_validate = CommandHelp {
+        name: Some("validate".into()),
+        args: None,
+        desc: Some("Check the config file for errors".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    config_subcommands.push(config_validate);
+
+    let config = CommandHelp {
+        name: Some("config".into()),
+        args: Some("<command>".into()),
+        desc: Some("Manage the config file".into()),
+        groups: vec!["Path options".into()],
+        subcommands: config_subcommands,
+    };
+    commands.push(config);
+
+    // completions
+    let completions = CommandHelp {
+        name: Some("completions".into()),
+        args: Some("<bash|zsh|fish>".into()),
+        desc: Some("Print a completion script for the given shell".into()),
+        groups: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    commands.push(completions);
+
+    return commands;
+}
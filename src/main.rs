@@ -1,10 +1,16 @@
 mod core;
+mod cli;
+mod cache_manager;
+mod frame_cache;
+mod progress;
+mod paths;
 mod help_options;
 mod fetch;
 
-use std::{panic, path::Path, io::Write};
-use lexopt::Arg::{Long, Short};
-use lexopt::ValueExt;
+use std::{panic, path::{Path, PathBuf}};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use fetch::config_manager::OptionsDefaults;
 
 const TERM_FONT_DIMS: (u16,u16) = (10,22);
 const MOFETCH_VERSION: &str = "1.4.1";
@@ -12,49 +18,193 @@ const MOFETCH_VERSION: &str = "1.4.1";
 /// TODO:
 /// * Cacheless rendering to terminal by just storing frame buffers in memory
 fn main() {
-    let args = parse_args().expect("Error: Use -i to specify input. Use -h for help");
+    let command = cli::parse_args().unwrap_or_else(|error| {
+        println!("Error: {}. Use \"mofetch --help\" for usage help", error);
+        std::process::exit(0);
+    });
+    match command {
+        cli::Command::Show(args) => show(args),
+        cli::Command::Render(args) => render(args),
+        cli::Command::Cache(command) => cache_command(command),
+        cli::Command::Gpus => {
+            let process_desc = pollster::block_on(core::ProcessDescriptor::init(0));
+            for gpu in process_desc.adapters_vec.iter().enumerate() {
+                println!("{}: {:?}",gpu.0, gpu.1.get_info());
+            }
+        }
+        cli::Command::Config(command) => config_command(command),
+        cli::Command::Completions(shell) => cli::print_completions(&shell),
+    }
+}
+
+/// Resolves the settings shared by `show` and `render` into a render request for a 
+/// terminal of `term_size_char`. The fps is the one from the arguments, not yet capped 
+/// to the source.
+fn render_request(args: &OptionsDefaults, term_size_char: (u16,u16)) -> RenderRequest {
+    let input = args.input.clone().unwrap();
+    let (max_width, max_height) = get_max_dims(term_size_char, args.max_width, args.max_height);
+
+    let equalize = parse_setting(&args.equalize);
+    let dither = parse_setting(&args.dither);
+    let background = parse_setting(&args.background);
+    let geometry = core::FrameGeometry {
+        fit: parse_setting(&args.fit),
+        crop: args.crop.as_ref().map(|crop| parse_setting(crop)),
+        target_cols: args.target_cols,
+        target_rows: args.target_rows,
+    };
+    let temporal = parse_setting(&args.temporal);
+    let charset = parse_setting(&args.charset);
+    if temporal == core::utils::Temporal::Ema && !(0.0..1.0).contains(&args.temporal_strength) {
+        println!("Error: --temporal-strength must be in 0..1 with --temporal ema");
+        std::process::exit(0);
+    }
+    let shader_config = core::utils::ShaderConfig {
+        brightness: args.brightness,
+        contrast: args.contrast,
+        gamma: args.gamma,
+        saturation: args.saturation,
+        invert: args.invert,
+        equalize,
+        sharpen: args.sharpen,
+        background,
+        charset,
+        draw_edges: args.draw_edges,
+        edge_threshold: args.edge_threshold,
+        dither,
+        shape_glyphs: args.shape_glyphs,
+        temporal,
+        temporal_strength: args.temporal_strength,
+        sigma: args.sigma,
+        sigma_ratio: args.sigma_ratio,
+        kernel_radius: args.kernel_radius,
+        dog_threshold: args.dog_threshold,
+        tau: args.tau,
+        phi: args.phi,
+        edge_bin_width: args.edge_bin_width,
+    };
+
+    let input_name = String::from(input.split('/').next_back().unwrap());
+    let cache_max_size = parse_setting::<cache_manager::ByteSize>(&args.cache_max_size).0;
+    return RenderRequest {
+        input,
+        cache_stem: paths::cache_dir().join(input_name),
+        frames_dir: paths::frames_dir("frames"),
+        fps: args.fps as f64,
+        start: args.start.clone(),
+        end: args.end.clone(),
+        geometry,
+        max_width,
+        max_height,
+        shader_config,
+        dump_stages: args.dump_stages.clone().map(|dir| core::DumpStages {
+            dir,
+            frames: args.dump_frames.clone().unwrap_or(vec![1]),
+        }),
+        verbose: args.verbose,
+        quiet: false,
+        settings: settings_summary(args),
+        cache_max_size: if cache_max_size == 0 {None} else {Some(cache_max_size)},
+    };
+}
+
+/// Short description of the render settings, stored in the cache index for `mofetch cache ls`.
+/// A cache whose summary differs from the current one is rendered again, so every setting
+/// that changes the frames has to be in here.
+fn settings_summary(args: &OptionsDefaults) -> String {
+    // the fps from the arguments, as the one capped to the source is only known after probing
+    let mut settings = vec![args.fit.clone(), format!("fps={}", args.fps)];
+    if let Some(start) = &args.start {settings.push(format!("start={}", start));}
+    if let Some(end) = &args.end {settings.push(format!("end={}", end));}
+    settings.push(format!("max={}x{}", args.max_width, args.max_height));
+    if let Some(crop) = &args.crop {
+        settings.push(format!("crop={}", crop));
+    }
+    if args.target_cols.is_some() || args.target_rows.is_some() {
+        let size = |target: Option<u16>| target.map(|n| n.to_string()).unwrap_or(String::from("auto"));
+        settings.push(format!("target={}x{}", size(args.target_cols), size(args.target_rows)));
+    }
+    settings.push(format!("brightness={} contrast={}", args.brightness, args.contrast));
+    if args.gamma != 1.0 {settings.push(format!("gamma={}", args.gamma));}
+    if args.saturation != 1.0 {settings.push(format!("saturation={}", args.saturation));}
+    if args.invert {settings.push(String::from("invert"));}
+    if args.equalize != "none" {settings.push(format!("equalize={}", args.equalize));}
+    if args.sharpen > 0.0 {settings.push(format!("sharpen={}", args.sharpen));}
+    if args.background != "transparent" {settings.push(format!("background={}", args.background));}
+    if let Some(profile) = &args.profile {
+        settings.insert(0, format!("profile={}", profile));
+    }
+    if args.charset != fetch::config_manager::DEFAULT_CHARSET {settings.push(format!("charset=\"{}\"", args.charset));}
+    settings.push(if args.draw_edges {format!("edges={}", args.edge_threshold)} else {String::from("no-edges")});
+    let kernel_radius = args.kernel_radius.map(|radius| radius.to_string()).unwrap_or(String::from("auto"));
+    settings.push(format!("sigma={} sigma-ratio={} kernel-radius={} dog-threshold={} tau={} phi={} edge-bin-width={}",
+        args.sigma, args.sigma_ratio, kernel_radius, args.dog_threshold, args.tau, args.phi, args.edge_bin_width));
+    if args.shape_glyphs {settings.push(String::from("shape-glyphs"));}
+    if args.dither != "none" {settings.push(format!("dither={}", args.dither));}
+    if args.temporal != "none" {settings.push(format!("temporal={} temporal-strength={}", args.temporal, args.temporal_strength));}
+    return settings.join(" ");
+}
+
+/// `mofetch render`: renders the input into the cache, replacing any existing cache of it
+fn render(args: OptionsDefaults) {
+    // fall back to a common terminal size when not attached to one, e.g. in scripts
+    let term_size_char = termion::terminal_size().unwrap_or((80, 24));
+    let mut render_request = render_request(&args, term_size_char);
+    render_request.fps = get_preferred_fps(&render_request.input, args.fps, args.verbose);
+
+    let process_desc = pollster::block_on(core::ProcessDescriptor::init(args.adapter_index));
+    std::fs::create_dir_all(paths::cache_dir()).ok();
+    for ext in [".afb", ".sfb"] {
+        std::fs::remove_file(paths::with_suffix(&render_request.cache_stem, ext)).ok();
+    }
+    let (cache_path, _) = create_cache(&render_request, &process_desc).unwrap_or_else(|error| {
+        println!("Error: {}", error);
+        std::process::exit(0);
+    });
+    if let Some(max_size) = render_request.cache_max_size {
+        cache_manager::prune(Some(max_size), None, &[&cache_path]);
+    }
+    println!("Cached to {}", cache_path.display());
+}
+
+/// `mofetch show`: plays the input next to the system info, rendering it first when there 
+/// is no usable cache
+fn show(args: OptionsDefaults) {
     let fps = args.fps;
-    let input = args.input.unwrap();
     let overwrite_cache = args.overwrite_cache;
-    let max_width = args.max_width;
-    let max_height: f32 = args.max_height;
+    let max_width_scale = args.max_width;
+    let max_height_scale: f32 = args.max_height;
     let adapter_index = args.adapter_index;
-    let brightness = args.brightness;
-    let contrast = args.contrast;
-    let draw_edges = args.draw_edges;
-    let edge_threshold = args.edge_threshold;
     let hide_info = args.hide_info;
     let verbose = args.verbose;
+    let reprocess_on_resize = args.reprocess_on_resize;
+    let progressive = args.progressive;
+    let progressive_frames = args.progressive_frames;
+    let playback_settings = core::PlaybackSettings {
+        loops: args.loops,
+        duration: args.duration,
+        speed: args.speed,
+        ping_pong: args.ping_pong,
+        show_fps: args.verbose,
+    };
 
     let term_size_char = termion::terminal_size().unwrap();
-
-    let term_width: u16 = term_size_char.0 * TERM_FONT_DIMS.0;
-    let max_width = (term_width as f32 * max_width).floor() as u16;
-
-    let term_height: u16 = (term_size_char.1 - 1) * TERM_FONT_DIMS.1;
-    let max_height = (term_height as f32 * max_height).floor() as u16;
-
-    let shader_config = core::utils::ShaderConfig {
-        brightness,
-        contrast,
-        draw_edges,
-        edge_threshold,
-    };
+    let mut render_request = render_request(&args, term_size_char);
+    let input = render_request.input.clone();
 
     // establish connection to GPU
-    let mut process_desc = pollster::block_on(core::ProcessDescriptor::init(adapter_index));
+    let process_desc = pollster::block_on(core::ProcessDescriptor::init(adapter_index));
+    let adapter_info = process_desc.adapter.get_info();
     
-    let input_name = input.split('/').last().unwrap();
-
     // afb: animated frame buffer
     // sfb: static frame buffer (for images)
-    let cache_dir = format!("{}/mofetch",dirs::cache_dir().unwrap().to_str().unwrap());
-    let cache_path_afb = format!("{}/{}.afb",&cache_dir,input_name);
-    let cache_path_sfb = format!("{}/{}.sfb",&cache_dir,input_name);
-    let mut cache_path: String = Default::default();
+    let cache_dir = paths::cache_dir();
+    let cache_path_afb = paths::with_suffix(&render_request.cache_stem, ".afb");
+    let cache_path_sfb = paths::with_suffix(&render_request.cache_stem, ".sfb");
+    let mut cache_path = PathBuf::new();
     let mut is_image = false;
-    let afb_path_exists = Path::new(&cache_path_afb).exists() && !overwrite_cache;
-    let sfb_path_exists = Path::new(&cache_path_sfb).exists() && !overwrite_cache;
+    let afb_path_exists = cache_path_afb.exists() && !overwrite_cache && !cache_manager::is_stale(&cache_path_afb, &input, &render_request.settings);
+    let sfb_path_exists = cache_path_sfb.exists() && !overwrite_cache && !cache_manager::is_stale(&cache_path_sfb, &input, &render_request.settings);
     
     // TODO: there is nothing to prevent this from happening right now. will have to fix later 
     // or come up with a smarter solution.
@@ -62,220 +212,520 @@ fn main() {
         panic!("Cache conflict: Input has 2 existing caches with different file formats");
     }
 
-    let mut cache_file: std::fs::File;
-
     // look for existing cache
     if afb_path_exists {
-        cache_path = format!("{}/{}.afb",&cache_dir,input_name);
+        cache_path = cache_path_afb;
     }
     else if sfb_path_exists {
-        cache_path = format!("{}/{}.sfb",&cache_dir,input_name);
+        cache_path = cache_path_sfb;
         is_image = true;
     }
 
-    let mut fps_preferred = fps;
-    if overwrite_cache {
+    let mut fps_preferred = fps as f64;
+    if overwrite_cache || (!afb_path_exists && !sfb_path_exists) {
         fps_preferred = get_preferred_fps(&input, fps, verbose);
     }
 
-    let ffmpeg_config = core::FfmpegConfig {
-        input_path: input.as_str(),
-        fps: &fps_preferred,
-    };
+    render_request.fps = fps_preferred;
+
+    let mut rendering = None;
+    let mut render_thread = None;
 
     // make cache file if it doesnt exist. make sfb or afb based on frame_count > 1
-    if !afb_path_exists && !sfb_path_exists {
-        let ffmpeg_return = core::get_frames(&ffmpeg_config, max_width, max_height, verbose);
-        if ffmpeg_return.frame_count == 1 {
-            is_image = true;
-            cache_path = format!("{}/{}.sfb",&cache_dir,input_name);
+    if !afb_path_exists && !sfb_path_exists && progressive {
+        std::fs::create_dir_all(&cache_dir).ok();
+        // ffmpeg and the per frame output would print over the playback
+        let mut request = render_request.clone();
+        request.verbose = false;
+        let mut render = start_render(&request);
+        is_image = render.is_image;
+        cache_path = render.cache_path.clone();
+        request.quiet = true;
+
+        // the temporary file is opened before the render can move it into place
+        let progress = render.cache.progress().unwrap();
+        let mut reader = frame_cache::CacheReader::open_rendering(&render.tmp_path, progress.clone()).unwrap();
+        render_thread = Some(std::thread::spawn(move || {
+            // a failed render is reported by the playback, which reads the cache
+            let Ok(cache_path) = finish_render(render, &request, &process_desc) else {
+                return;
+            };
+            if let Some(max_size) = request.cache_max_size {
+                cache_manager::prune(Some(max_size), None, &[&cache_path]);
+            }
+        }));
+
+        // playback starts once the first frames are rendered, or the whole render if it is shorter.
+        // Images are rendered before they are shown
+        let start_frames = if is_image {usize::MAX} else {progressive_frames};
+        while progress.frames() < start_frames && !progress.is_done() && !progress.has_failed() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
         }
-        else {
-            cache_path = format!("{}/{}.afb",&cache_dir,input_name);
+        reader.refresh().expect("Error: Could not render the thumbnail");
+        rendering = Some(reader);
+    }
+    else if !afb_path_exists && !sfb_path_exists {
+        std::fs::create_dir_all(&cache_dir).ok();
+        (cache_path, is_image) = create_cache(&render_request, &process_desc).unwrap_or_else(|error| {
+            println!("Error: {}", error);
+            std::process::exit(0);
+        });
+        if let Some(max_size) = render_request.cache_max_size {
+            cache_manager::prune(Some(max_size), None, &[&cache_path]);
         }
-
-        std::fs::create_dir(&cache_dir).ok();
-        std::fs::File::create(&cache_path).unwrap();
-
-        cache_file = std::fs::File::options().append(true).open(&cache_path).unwrap(); // w cache file
-        cache_file.write(format!("[] fps={}\n",fps_preferred).as_bytes()).ok(); // write fps config in cache file
-
-        process_desc = pollster::block_on(core::ProcessDescriptor::init(adapter_index));
-
-        core::process_frames(&ffmpeg_return.frame_count, &process_desc, cache_file,
-            ffmpeg_return.width, ffmpeg_return.height, max_width, max_height, shader_config,
-            verbose,
-        );
     }
-
-    let adapter_clone = process_desc.adapter.clone();
-    let frame_dims = read_frame_size(&cache_path);
-    std::thread::spawn(move || {
-        if hide_info || is_image {return;}
-        fetch::sys_info_manager(adapter_clone.get_info(), frame_dims.0, frame_dims.1);
-    });
-    core::print_frame_loop(&cache_path, is_image);
-
-    // this should be inaccessible unless is_image is true
-    if !hide_info {
-        fetch::sys_info_manager(process_desc.adapter.get_info(), frame_dims.0, frame_dims.1);
+    else {
+        cache_manager::touch(&cache_path);
     }
-}
 
-/// Uses ffprobe to retrieve source fps and caps the user-defined fps with it. 
-/// This prevents unecessarily large framerates.
-fn get_preferred_fps(input_path: &String, user_fps: u16, verbose: bool) -> u16 {
-    let fps_stdout = std::process::Command::new("ffprobe")
-        .args(["-v","error","-of","default=noprint_wrappers=1:nokey=1",
-            "-show_entries","stream=r_frame_rate",input_path.as_str()])
-        .stdout(std::process::Stdio::piped())
-        .output()
-        .unwrap(); // this should get an output like "30/1"
+    // re-render in the background whenever the terminal is resized
+    let resized = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGWINCH, resized.clone()).unwrap();
+
+    let (variant_tx, variant_rx) = std::sync::mpsc::channel();
+    let on_resize: Option<core::ResizeCallback> = if reprocess_on_resize {
+        let resize_queue = Arc::new(Mutex::new(ResizeQueue::default()));
+        let initial_cache = cache_path.clone();
+        Some(Box::new(move |term_size| {
+            let mut queue = resize_queue.lock().unwrap();
+            queue.term_size = Some(term_size);
+            if queue.running {
+                return;
+            }
+            queue.running = true;
+
+            let resize_queue = resize_queue.clone();
+            let variant_tx = variant_tx.clone();
+            let initial_cache = initial_cache.clone();
+            let mut request = render_request.clone();
+            request.frames_dir = paths::frames_dir("frames_resize");
+            request.dump_stages = None;
+            request.verbose = false;
+            request.quiet = true;
+            std::thread::spawn(move || {
+                let process_desc = pollster::block_on(core::ProcessDescriptor::init(adapter_index));
+                let mut rendered_sizes = Vec::new();
+                loop {
+                    // only the latest size is rendered if several resizes come in during a render
+                    let term_size = {
+                        let mut queue = resize_queue.lock().unwrap();
+                        match queue.term_size.take() {
+                            Some(term_size) => term_size,
+                            None => {
+                                queue.running = false;
+                                return;
+                            }
+                        }
+                    };
 
-    let fps_output = String::from_utf8(fps_stdout.stdout).unwrap();
-    let fps_source: Vec<&str> = fps_output.split('/').collect();
-    let fps_denominator = fps_source[1].trim().parse::<u16>().unwrap();
-    let mut fps_source = fps_source[0].parse::<u16>().unwrap();
+                    let variant_path = if term_size == term_size_char {
+                        // with --progressive, the initial cache may still be rendering. Playback 
+                        // stays with the size variant until it is done
+                        if !initial_cache.exists() {
+                            continue;
+                        }
+                        initial_cache.clone()
+                    }
+                    else {
+                        let mut variant_request = request.clone();
+                        (variant_request.max_width, variant_request.max_height) =
+                            get_max_dims(term_size, max_width_scale, max_height_scale);
+                        variant_request.cache_stem = paths::with_suffix(&request.cache_stem, &format!("@{}x{}", term_size.0, term_size.1));
+
+                        // size variants from an earlier run may have been made with different settings
+                        let existing = [".afb", ".sfb"].iter()
+                            .map(|ext| paths::with_suffix(&variant_request.cache_stem, ext))
+                            .find(|path| path.exists());
+                        match existing {
+                            Some(path) if (!overwrite_cache && !cache_manager::is_stale(&path, &variant_request.input, &variant_request.settings))
+                                || rendered_sizes.contains(&term_size) => {
+                                cache_manager::touch(&path);
+                                path
+                            }
+                            _ => {
+                                // playback stays with the current cache when the render fails
+                                let Ok((path, _)) = create_cache(&variant_request, &process_desc) else {
+                                    resize_queue.lock().unwrap().running = false;
+                                    return;
+                                };
+                                if let Some(max_size) = variant_request.cache_max_size {
+                                    cache_manager::prune(Some(max_size), None, &[&path, &initial_cache]);
+                                }
+                                path
+                            }
+                        }
+                    };
+                    rendered_sizes.push(term_size);
 
-    // output can be a fractional value, so we floor it
-    fps_source = (fps_source as f32 / fps_denominator as f32).floor() as u16;
+                    if variant_tx.send(variant_path).is_err() {
+                        return;
+                    }
+                }
+            });
+        }))
+    } else {
+        None
+    };
 
-    let fps_preferred =
-        if user_fps > fps_source {
-            if verbose {println!("Chosen fps is greater than source fps. Capping fps to {}",fps_source);}
-            fps_source
-        }
-        else { user_fps };
-    return fps_preferred;
-}
+    let shared_layout = Arc::new(core::SharedLayout::default());
+    let info_thread = if !hide_info {
+        let shared_layout = shared_layout.clone();
+        let profile = args.profile.clone();
+        Some(std::thread::spawn(move || {
+            fetch::sys_info_manager(adapter_info, shared_layout, profile);
+        }))
+    } else {
+        None
+    };
 
-/// Reads the first frame to return the dimensions of the cache frames.
-pub fn read_frame_size(path: &str) -> (u32,u32) {
-    let file_string = std::fs::read_to_string(path).unwrap();
-    let mut render_width = 0;
-    let mut render_height = 0;
-    let mut frame_state = false;
-    for line in file_string.lines() {
-
-        // if line is not a config line AND line is not a frame separator
-        if !line.contains("[]") && line.len() > 1 {
-            let new_render_width = std::cmp::max(line.len() as u32, render_width);
-            render_width = new_render_width; // buffer alignment bug that causes the last line in a frame to sometimes be shorter than its supposed to be
-            render_height += 1;
-            frame_state = true;
-        }
+    core::print_frame_loop(&cache_path, is_image, core::PlaybackContext {
+        shared_layout,
+        hide_info,
+        info_thread,
+        settings: playback_settings,
+        resized,
+        variant_rx: Some(variant_rx),
+        on_resize,
+        rendering,
+    });
 
-        // once reader reached the end of the first frame separator, end loop and register dims
-        else if line.len() <= 1 && frame_state == true {
-            break;
-        }
+    // the cache is only usable once it is complete, so a render that outlasts the playback 
+    // is finished before exiting
+    if let Some(render_thread) = render_thread {
+        render_thread.join().ok();
     }
-    return (render_width, render_height);
 }
 
-use fetch::config_manager::OptionsDefaults;
-fn parse_args() -> Result<OptionsDefaults, lexopt::Error> {
-    let mut parser = lexopt::Parser::from_env();
-
-    let mut config = fetch::get_config_defaults();
-
-    while let Some(arg) = parser.next()? {
-        match arg {
-            Short('v') | Long("verbose") => {
-                config.verbose = true;
+/// `mofetch cache ...`
+fn cache_command(command: cli::CacheCommand) {
+    match command {
+        cli::CacheCommand::Ls => {
+            let entries = cache_manager::entries();
+            if entries.is_empty() {
+                println!("No caches in {}", paths::cache_dir().display());
+                return;
             }
-            Short('V') | Long("version") => {
-                println!("mofetch {}",MOFETCH_VERSION);
-                std::process::exit(0);
+            println!("INPUT                        VARIANT  CHARS     FRAMES     FPS       SIZE LAST USED  SETTINGS");
+            for entry in entries.iter() {
+                let (cols, rows, frames, fps) = cache_manager::stats(entry);
+                let settings = if entry.partial {"(unfinished render)"} else {entry.record.as_ref().map(|record| record.settings.as_str()).unwrap_or("-")};
+                println!("{:<28} {:<8} {:<9} {:>6} {:>7.2} {:>10} {:<10} {}", entry.input, cache_manager::variant_name(entry),
+                    format!("{}x{}", cols, rows), frames, fps, cache_manager::format_size(entry.size),
+                    cache_manager::format_age(entry.last_used), settings);
             }
-            Short('i') | Long("input") => {
-                config.input = Some(parser.value()?.parse()?);
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            println!("{} caches, {} in {}", entries.len(), cache_manager::format_size(total), paths::cache_dir().display());
+        }
+        cli::CacheCommand::Rm(input) => {
+            let input_name = input.split('/').next_back().unwrap();
+            match cache_manager::remove_input(input_name) {
+                0 => println!("No caches of {}", input_name),
+                removed => println!("Removed {} cache(s) of {}", removed, input_name),
             }
-            Short('o') | Long("overwrite-cache") => {
-                config.overwrite_cache = true;
+        }
+        cli::CacheCommand::Clear => {
+            match cache_manager::clear() {
+                Ok(()) => println!("Cleared {}", paths::cache_dir().display()),
+                Err(error) => println!("Error: could not clear {}: {}", paths::cache_dir().display(), error),
             }
-            Short('f') | Long("fps") => {
-                config.fps = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+        }
+        cli::CacheCommand::Info(input) => {
+            let input_name = input.split('/').next_back().unwrap();
+            let entries: Vec<_> = cache_manager::entries().into_iter().filter(|entry| entry.input == input_name).collect();
+            if entries.is_empty() {
+                println!("No caches of {}", input_name);
+                return;
             }
-            Short('b') | Long("brightness") => {
-                config.brightness = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+            for entry in entries.iter() {
+                let (cols, rows, frames, fps) = cache_manager::stats(entry);
+                println!("{}", entry.path.display());
+                println!("  type: {} | variant: {} | frames: {} | fps: {:.3} | size: {}x{} chars | file size: {} | last used: {}",
+                    if entry.is_image {"image"} else {"video"}, cache_manager::variant_name(entry), frames, fps,
+                    cols, rows, cache_manager::format_size(entry.size), cache_manager::format_age(entry.last_used));
+                if let Some(record) = &entry.record {
+                    println!("  source: {}", record.source);
+                    println!("  settings: {}", record.settings);
+                }
             }
-            Short('c') | Long("contrast") => {
-                config.contrast = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+        }
+        cli::CacheCommand::Warm(dir) => {
+            let dir = fetch::config_manager::expand_home(&dir);
+            let files = core::media_files(&dir).unwrap_or_else(|error| {
+                println!("Error: could not read {}: {}", dir.display(), error);
+                std::process::exit(0);
+            });
+            // caches are rendered for the current terminal, like `mofetch render` does
+            let term_size_char = termion::terminal_size().unwrap_or((80, 24));
+            let config = fetch::config_manager::retrieve_config(None);
+            let mut process_desc = None;
+            let mut rendered = Vec::new();
+            let mut failed = 0;
+            for (index, file) in files.iter().enumerate() {
+                let Some(input) = file.to_str() else {
+                    println!("Skipping {}, not a valid UTF-8 path", file.display());
+                    continue;
+                };
+                let profile = fetch::config_manager::input_profile(&config, input);
+                let mut args = fetch::get_config_defaults(profile.as_deref());
+                args.input = Some(String::from(input));
+                let mut request = render_request(&args, term_size_char);
+                let cached = [".afb", ".sfb"].iter()
+                    .map(|ext| paths::with_suffix(&request.cache_stem, ext))
+                    .any(|path| path.exists() && !cache_manager::is_stale(&path, &request.input, &request.settings));
+                if cached {
+                    println!("[{}/{}] {} is cached", index + 1, files.len(), input);
+                    continue;
+                }
+                println!("[{}/{}] {}", index + 1, files.len(), input);
+                request.fps = get_preferred_fps(&request.input, args.fps, args.verbose);
+                // the GPU is only set up once something has to be rendered
+                let process_desc = process_desc.get_or_insert_with(|| {
+                    std::fs::create_dir_all(paths::cache_dir()).ok();
+                    return pollster::block_on(core::ProcessDescriptor::init(args.adapter_index));
+                });
+                match create_cache(&request, process_desc) {
+                    Ok((cache_path, _)) => rendered.push(cache_path),
+                    Err(error) => {
+                        println!("Error: could not render {}: {}", input, error);
+                        failed += 1;
+                    }
+                }
             }
-            Short('n') | Long("no-edges") => {
-                config.draw_edges = false;
-                config.overwrite_cache = true;
+            // the caches that were just rendered are kept even when they go over the size cap
+            let args = fetch::get_config_defaults(None);
+            let max_size = parse_setting::<cache_manager::ByteSize>(&args.cache_max_size).0;
+            if max_size > 0 {
+                let keep: Vec<&Path> = rendered.iter().map(PathBuf::as_path).collect();
+                cache_manager::prune(Some(max_size), None, &keep);
             }
-            Short('t') | Long("edge-threshold") => {
-                config.edge_threshold = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+            println!("Rendered {} cache(s), {} of {} media files were cached already", rendered.len(), files.len() - rendered.len() - failed, files.len());
+            if failed > 0 {
+                println!("{} media file(s) could not be rendered", failed);
             }
-            Short('W') | Long("max-width") => {
-                config.max_width = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+        }
+        cli::CacheCommand::Prune { max_size, older_than } => {
+            // without any limits, evict down to the configured size cap
+            let max_size = match (max_size, older_than) {
+                (None, None) => {
+                    let args = fetch::get_config_defaults(None);
+                    Some(parse_setting::<cache_manager::ByteSize>(&args.cache_max_size).0).filter(|size| *size > 0)
+                }
+                _ => max_size,
+            };
+            let removed = cache_manager::prune(max_size, older_than, &[]);
+            let size: u64 = removed.iter().map(|entry| entry.size).sum();
+            for entry in removed.iter() {
+                println!("Removed {}", entry.path.display());
             }
-            Short('H') | Long("max-height") => {
-                config.max_height = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+            println!("Removed {} cache(s), {}", removed.len(), cache_manager::format_size(size));
+        }
+    }
+}
+
+/// `mofetch config ...`
+fn config_command(command: cli::ConfigCommand) {
+    let config_path = paths::config_path();
+    match command {
+        cli::ConfigCommand::Init => {
+            match fetch::config_manager::init_config() {
+                Ok(true) => println!("Wrote the default config to {}", config_path.display()),
+                Ok(false) => println!("{} already exists", config_path.display()),
+                Err(error) => println!("Error: could not write {}: {}", config_path.display(), error),
             }
-            Short('I') | Long("hide-info") => {
-                config.hide_info = true;
+        }
+        cli::ConfigCommand::Path => println!("{}", config_path.display()),
+        cli::ConfigCommand::Edit => {
+            if let Err(error) = fetch::config_manager::init_config() {
+                println!("Error: could not write {}: {}", config_path.display(), error);
+                return;
             }
-            Long("gpus") => {
-                let process_desc = pollster::block_on(core::ProcessDescriptor::init(0));
-                for gpu in process_desc.adapters_vec.iter().enumerate() {
-                    println!("{}: {:?}",gpu.0, gpu.1.get_info());
-                }
-                std::process::exit(0);
+            let editor = std::env::var("VISUAL").or(std::env::var("EDITOR")).unwrap_or(String::from("vi"));
+            if let Err(error) = std::process::Command::new(&editor).arg(&config_path).status() {
+                println!("Error: could not run {}: {}", editor, error);
             }
-            Short('a') | Long("adapter-index") => {
-                config.adapter_index = parser.value()?.parse()?;
-                config.overwrite_cache = true;
+        }
+        cli::ConfigCommand::Validate => {
+            let Ok(config_str) = std::fs::read_to_string(&config_path) else {
+                println!("There is no config at {}, the defaults are used", config_path.display());
+                return;
+            };
+            if let Some((version, _)) = fetch::config_manager::migrate_config(&config_str) {
+                println!("{} is config version {}, it will be migrated to version {} the next time it is loaded",
+                    config_path.display(), version, fetch::config_manager::CONFIG_VERSION);
             }
-
-            Short('h') | Short('?') | Long("help") => {
-                let help_intro = String::from("mofetch is a system information fetching tool with fancy user-generated ASCII art");
-                let help_usage = String::from("Usage: mofetch [-i path-to-input] [options]");
-                println!("{}",help_intro);
-                println!("{}",help_usage);
-                println!("\nNOTE: Specifying pre-processing or shader options will force --overwrite-cache.");
-
-                let options = help_options::init_options();
-                for group in options {
-                    println!("\n{}",group.name.unwrap());
-                    for option in group.options {
-                        let short = if option.short.is_some() {
-                            format!("-{}",option.short.unwrap())
-                        } else {
-                            String::from("  ")
-                        };
-                        let datatype = if option.datatype.is_some() {
-                            format!(" <{}>",option.datatype.unwrap())
-                        } else {
-                            String::from("")
-                        };
-                        let pre_desc_text = format!("{0} --{2}{1}",short,datatype,option.long.unwrap());
-                        let space_count = 29 - pre_desc_text.len();
-                        let mut spaces: String = Default::default();
-                        for _ in 1..space_count {
-                            spaces += " ";
-                        }
-                        println!("{0}{1}{2}", pre_desc_text, spaces, option.desc.unwrap());
+            match fetch::config_manager::parse_config(&config_str) {
+                Ok((_, warnings)) => {
+                    for warning in warnings.iter() {
+                        println!("Warning: {}", warning);
                     }
+                    println!("{} is valid", config_path.display());
                 }
-                std::process::exit(0);
+                Err(error) => println!("Error in {}: {}", config_path.display(), error),
             }
-            _ => return Err(arg.unexpected()),
         }
     }
-    if config.input.is_none() {
-        println!("Error: Expected file input. Use \"mofetch --help\" for usage help");
+}
+
+/// Parses a setting that can come from the command line or the config file, exiting 
+/// with the parse error if it is invalid.
+fn parse_setting<T: std::str::FromStr<Err = String>>(value: &str) -> T {
+    return value.parse().unwrap_or_else(|error| {
+        println!("Error: {}", error);
         std::process::exit(0);
+    });
+}
+
+/// Converts the user-defined max width and height (0..1) into pixels, based on 
+/// the terminal size in chars.
+fn get_max_dims(term_size_char: (u16,u16), max_width: f32, max_height: f32) -> (u16,u16) {
+    let term_width: u16 = term_size_char.0 * TERM_FONT_DIMS.0;
+    let max_width = (term_width as f32 * max_width).floor() as u16;
+
+    let term_height: u16 = (term_size_char.1 - 1) * TERM_FONT_DIMS.1;
+    let max_height = (term_height as f32 * max_height).floor() as u16;
+    return (max_width, max_height);
+}
+
+/// Everything needed to render an input into a frame buffer cache.
+#[derive(Clone)]
+struct RenderRequest {
+    input: String,
+    /// cache path without the .afb/.sfb extension
+    cache_stem: PathBuf,
+    frames_dir: PathBuf,
+    fps: f64,
+    start: Option<String>,
+    end: Option<String>,
+    geometry: core::FrameGeometry,
+    max_width: u16,
+    max_height: u16,
+    shader_config: core::utils::ShaderConfig,
+    dump_stages: Option<core::DumpStages>,
+    verbose: bool,
+    /// suppress progress messages, used when rendering behind the playback
+    quiet: bool,
+    /// recorded in the cache index
+    settings: String,
+    /// caches are evicted down to this size after rendering
+    cache_max_size: Option<u64>,
+}
+
+#[derive(Default)]
+struct ResizeQueue {
+    term_size: Option<(u16,u16)>,
+    running: bool,
+}
+
+/// A cache being rendered: ffmpeg is extracting the frames of the source, which are 
+/// processed into a temporary file until the cache is complete.
+struct CacheRender {
+    source: core::FFmpegReturn,
+    cache: frame_cache::CacheWriter,
+    info: frame_cache::CacheInfo,
+    cache_path: PathBuf,
+    tmp_path: PathBuf,
+    is_image: bool,
+}
+
+/// Starts breaking the input down into frames and creates the cache they are processed into
+fn start_render(request: &RenderRequest) -> CacheRender {
+    let ffmpeg_config = core::FfmpegConfig {
+        input_path: request.input.as_str(),
+        fps: &request.fps,
+        frames_path: &request.frames_dir,
+        start: request.start.as_deref(),
+        end: request.end.as_deref(),
+        geometry: request.geometry,
+    };
+
+    if !request.quiet {println!("Processing source...");}
+    let ffmpeg_return = core::get_frames(&ffmpeg_config, request.max_width, request.max_height, request.verbose);
+    // the input is an image when ffmpeg is done after a single frame
+    let is_image = !ffmpeg_return.frames.wait_for(1);
+    let cache_path = paths::with_suffix(&request.cache_stem, if is_image {".sfb"} else {".afb"});
+
+    // frames are written to a temporary file first, so an interrupted render never 
+    // leaves a half-written cache behind
+    let tmp_path = paths::with_suffix(&cache_path, ".tmp");
+    let info = frame_cache::CacheInfo {
+        fps: request.fps,
+        cols: ffmpeg_return.width as u32 / ffmpeg_return.tile_res.x,
+        rows: ffmpeg_return.height as u32 / ffmpeg_return.tile_res.y,
+    };
+    let cache = frame_cache::CacheWriter::create(&tmp_path, info).unwrap();
+
+    return CacheRender {
+        source: ffmpeg_return,
+        cache,
+        info,
+        cache_path,
+        tmp_path,
+        is_image,
+    };
+}
+
+/// Processes the frames of a render into its cache. Returns the cache path. A render that 
+/// fails leaves its temporary file behind, which `mofetch cache prune` removes.
+fn finish_render(mut render: CacheRender, request: &RenderRequest, process_desc: &core::ProcessDescriptor) -> Result<PathBuf, String> {
+    if !request.quiet {println!("Processing frames...");}
+    core::process_frames(&render.source, process_desc, &mut render.cache, request.shader_config.clone(), request.dump_stages.as_ref(), request.verbose,
+        !request.quiet,
+    )?;
+    let (frames, cache_size) = render.cache.finish().unwrap();
+    std::fs::rename(&render.tmp_path, &render.cache_path).unwrap();
+    let info = render.info;
+    if request.verbose {
+        let raw_size = frames as u64 * (info.cols as u64 + 1) * info.rows as u64;
+        println!("Cache size: {} ({:.1}x smaller than plain text)",
+            cache_manager::format_size(cache_size), raw_size as f64 / cache_size.max(1) as f64);
     }
 
-    Ok(config)
-}
\ No newline at end of file
+    cache_manager::record_render(cache_manager::IndexEntry {
+        file: render.cache_path.file_name().unwrap().to_string_lossy().into_owned(),
+        source: cache_manager::source_key(&request.input),
+        settings: request.settings.clone(),
+        cols: info.cols,
+        rows: info.rows,
+        frames,
+        fps: request.fps,
+        created: 0,
+        last_used: 0,
+    });
+
+    return Ok(render.cache_path);
+}
+
+/// Breaks the input down into frames and caches the processed frame buffers. Returns 
+/// the cache path and whether the input is an image.
+fn create_cache(request: &RenderRequest, process_desc: &core::ProcessDescriptor) -> Result<(PathBuf, bool), String> {
+    let render = start_render(request);
+    let is_image = render.is_image;
+    return Ok((finish_render(render, request, process_desc)?, is_image));
+}
+
+/// Uses ffprobe to retrieve source fps and caps the user-defined fps with it. 
+/// This prevents unecessarily large framerates. Fractional source framerates 
+/// (e.g. 30000/1001) are kept as they are.
+fn get_preferred_fps(input_path: &String, user_fps: u16, verbose: bool) -> f64 {
+    let fps_stdout = std::process::Command::new("ffprobe")
+        .args(["-v","error","-select_streams","v:0","-of","default=noprint_wrappers=1:nokey=1",
+            "-show_entries","stream=r_frame_rate",input_path.as_str()])
+        .stdout(std::process::Stdio::piped())
+        .output()
+        .unwrap(); // this should get an output like "30/1"
+
+    let fps_output = String::from_utf8(fps_stdout.stdout).unwrap();
+    let fps_line = fps_output.lines().next().unwrap_or("");
+    let fps_source: Vec<&str> = fps_line.split('/').collect();
+    let fps_numerator = fps_source[0].trim().parse::<f64>().unwrap_or(0.0);
+    let fps_denominator = fps_source.get(1).and_then(|d| d.trim().parse::<f64>().ok()).unwrap_or(1.0);
+    let fps_source = fps_numerator / fps_denominator;
+
+    let fps_preferred =
+        if fps_source.is_finite() && fps_source > 0.0 && user_fps as f64 > fps_source {
+            if verbose {println!("Chosen fps is greater than source fps. Capping fps to {:.3}",fps_source);}
+            fps_source
+        }
+        else { user_fps as f64 };
+    return fps_preferred;
+}
@@ -0,0 +1,67 @@
+//! Where mofetch keeps its files. The config, cache and data locations can be overridden
+//! with `--config`/`--cache-dir`/`--data-dir`, then with `MOFETCH_CONFIG`/`MOFETCH_CACHE_DIR`/
+//! `MOFETCH_DATA_DIR`, and otherwise follow the platform conventions (`$XDG_CONFIG_HOME`,
+//! `$XDG_CACHE_HOME` and `$XDG_DATA_HOME` on linux).
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the paths given on the command line. Has to be called before any path is used.
+pub fn set_overrides(config_path: Option<PathBuf>, cache_dir: Option<PathBuf>, data_dir: Option<PathBuf>) {
+    if let Some(config_path) = config_path {
+        CONFIG_PATH.set(config_path).ok();
+    }
+    if let Some(cache_dir) = cache_dir {
+        CACHE_DIR.set(cache_dir).ok();
+    }
+    if let Some(data_dir) = data_dir {
+        DATA_DIR.set(data_dir).ok();
+    }
+}
+
+/// The config file
+pub fn config_path() -> PathBuf {
+    if let Some(path) = CONFIG_PATH.get().cloned().or_else(|| env_path("MOFETCH_CONFIG")) {
+        return path;
+    }
+    return platform_dir(dirs::config_dir()).join("mofetch").join("config.toml");
+}
+
+/// Directory of the .afb/.sfb caches and their index
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = CACHE_DIR.get().cloned().or_else(|| env_path("MOFETCH_CACHE_DIR")) {
+        return dir;
+    }
+    return platform_dir(dirs::cache_dir()).join("mofetch");
+}
+
+/// Directory for the png frames of a processing run
+pub fn frames_dir(name: &str) -> PathBuf {
+    if let Some(dir) = DATA_DIR.get().cloned().or_else(|| env_path("MOFETCH_DATA_DIR")) {
+        return dir.join(name);
+    }
+    return platform_dir(dirs::data_dir()).join("mofetch").join(name);
+}
+
+/// `path` with `suffix` appended to its file name, e.g. `clip.gif` to `clip.gif.afb`.
+/// Unlike `Path::with_extension`, the extension of the input is kept.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    return PathBuf::from(path);
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    return std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+}
+
+// without a home directory there is no platform directory, e.g. in some sandboxes and
+// service accounts. mofetch still runs from the temp directory then
+fn platform_dir(dir: Option<PathBuf>) -> PathBuf {
+    return dir.unwrap_or_else(std::env::temp_dir);
+}
@@ -0,0 +1,81 @@
+//! Progress line for processing: frames done out of the total, the frames ffmpeg has
+//! extracted so far, the processing rate and the time left. It is drawn over itself on
+//! stderr, and only when stderr is a terminal.
+
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// the line is redrawn at most this often, so fast renders don't spend their time drawing it
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 24;
+
+pub struct ProgressBar {
+    label: &'static str,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressBar {
+    /// Returns `None` when stderr is not a terminal, e.g. when it is redirected to a file
+    pub fn new(label: &'static str) -> Option<ProgressBar> {
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+        return Some(ProgressBar {
+            label,
+            start: Instant::now(),
+            last_draw: None,
+        });
+    }
+
+    /// Redraws the line with `done` frames processed and `decoded` extracted. The total is
+    /// the exact frame count once ffmpeg is done, or the ffprobe estimate before that.
+    pub fn update(&mut self, done: usize, decoded: usize, total: Option<usize>) {
+        if self.last_draw.is_some_and(|last_draw| last_draw.elapsed() < REDRAW_INTERVAL) {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let frames_per_second = if elapsed > 0.0 {done as f64 / elapsed} else {0.0};
+        // the estimate can be off by a few frames, so it never reads less than what is there
+        let total = total.map(|total| total.max(done).max(decoded));
+
+        let mut line = String::from(self.label);
+        if let Some(total) = total.filter(|total| *total > 0) {
+            let filled = BAR_WIDTH * done / total;
+            line += &format!(" [{}{}] {}/{} frames (extracted {}) | {:.1} frames/s",
+                "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), done, total, decoded, frames_per_second);
+            if frames_per_second > 0.0 {
+                let left = Duration::from_secs_f64((total - done) as f64 / frames_per_second);
+                line += &format!(" | ETA {}", format_duration(left));
+            }
+        }
+        else {
+            line += &format!(" {} frames (extracted {}) | {:.1} frames/s", done, decoded, frames_per_second);
+        }
+
+        // a line that wraps could not be drawn over anymore
+        let width = termion::terminal_size().map(|(cols, _)| cols as usize).unwrap_or(80);
+        line.truncate(width.saturating_sub(1));
+        eprint!("\r{}{}", termion::clear::CurrentLine, line);
+        std::io::stderr().flush().ok();
+    }
+
+    /// Clears the line
+    pub fn finish(self) {
+        eprint!("\r{}", termion::clear::CurrentLine);
+        std::io::stderr().flush().ok();
+    }
+}
+
+/// Formats a duration like `1:05` or `1:02:05`
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    return if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    }
+    else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    };
+}
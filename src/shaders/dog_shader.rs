@@ -1,24 +1,79 @@
 use wgpu::{util::DeviceExt};
 
-#[path = "../utils.rs"]
-mod utils;
+// Largest blur radius the weight table has room for. The weights array in the DoG wgsl 
+// files has MAX_KERNEL_RADIUS + 1 entries
+pub const MAX_KERNEL_RADIUS: u32 = 32;
 
+/// The horizontal blur pass renders to a float texture, so both blurs keep their precision
+/// until they are subtracted.
+pub const BLUR_TARGETS: [Option<wgpu::ColorTargetState>; 1] = [Some(wgpu::ColorTargetState {
+    format: wgpu::TextureFormat::Rgba16Float,
+    blend: Some(wgpu::BlendState::REPLACE),
+    write_mask: wgpu::ColorWrites::ALL,
+})];
+
+/// Precomputed gaussian weights for both blurs, shared by the horizontal and vertical
+/// passes. Must match `BlurSettings` in the DoG wgsl files.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct ResBinding {
+struct BlurSettingsBinding {
     resolution: [f32; 2],
+    radius: i32,
+    _padding: i32,
+    // x: weak blur, y: strong blur, for each offset from the center
+    weights: [[f32; 4]; MAX_KERNEL_RADIUS as usize + 1],
+}
+
+/// Must match `DogSettings` in dog_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DogSettingsBinding {
+    threshold: f32,
+    tau: f32,
+    phi: f32,
+    _padding: f32,
 }
 
 pub struct DogShader<'a> {
+    pub blur_pipeline: &'a wgpu::RenderPipeline,
+    pub blur_bind_group: wgpu::BindGroup,
+    pub blur_target: wgpu::Texture,
     pub pipeline: &'a wgpu::RenderPipeline,
     pub bind_group: wgpu::BindGroup,
     pub render_target: wgpu::Texture,
-    pub output_buffer: wgpu::Buffer,
+}
+
+// normalized probability density function
+fn norm_pdf(x: f32, sigma: f32) -> f32 {
+    return 0.39894 * (-0.5 * x * x / (sigma*sigma)).exp() / sigma;
+}
+
+/// Normalized 1D gaussian weights for offsets 0..=radius. Each blur is cut off at 3 sigma
+/// or at the user-defined radius, whichever is smaller.
+fn gaussian_weights(sigma: f32, max_radius: u32) -> [f32; MAX_KERNEL_RADIUS as usize + 1] {
+    let mut weights = [0.0; MAX_KERNEL_RADIUS as usize + 1];
+    if sigma <= 0.0 {
+        weights[0] = 1.0; // no blur
+        return weights;
+    }
+
+    let radius = std::cmp::min((3.0 * sigma).ceil() as u32, max_radius) as usize;
+    for (i, weight) in weights.iter_mut().enumerate().take(radius + 1) {
+        *weight = norm_pdf(i as f32, sigma);
+    }
+
+    // the kernel covers both sides of the center
+    let sum = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    for weight in weights.iter_mut() {
+        *weight /= sum;
+    }
+    return weights;
 }
 
 pub fn new<'a>(
     device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Extent3d,
-    pipeline: &'a wgpu::RenderPipeline, 
+    blur_pipeline: &'a wgpu::RenderPipeline, pipeline: &'a wgpu::RenderPipeline,
+    shader_config: &super::utils::ShaderConfig,
 ) -> DogShader<'a> {
 
     // bindgroup entries
@@ -34,16 +89,57 @@ pub fn new<'a>(
     });
 
     // buffers
-    let resolution = ResBinding {
+    // without a radius, each blur is only cut off at 3 sigma
+    let radius = std::cmp::min(shader_config.kernel_radius.unwrap_or(MAX_KERNEL_RADIUS), MAX_KERNEL_RADIUS);
+    let weak_weights = gaussian_weights(shader_config.sigma, radius);
+    let strong_weights = gaussian_weights(shader_config.sigma * shader_config.sigma_ratio, radius);
+    let mut weights = [[0.0; 4]; MAX_KERNEL_RADIUS as usize + 1];
+    for (i, weight) in weights.iter_mut().enumerate() {
+        *weight = [weak_weights[i], strong_weights[i], 0.0, 0.0];
+    }
+
+    // taps past 3 sigma of the strong blur have no weight, so they are skipped
+    let strong_sigma = f32::max(shader_config.sigma, shader_config.sigma * shader_config.sigma_ratio);
+    let loop_radius = std::cmp::min((3.0 * strong_sigma).ceil() as u32, radius);
+
+    let blur_settings = BlurSettingsBinding {
         resolution: [size.width as f32, size.height as f32],
+        radius: loop_radius as i32,
+        _padding: 0,
+        weights,
+    };
+    let blur_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("blur buffer"),
+        contents: bytemuck::cast_slice(&[blur_settings]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let settings = DogSettingsBinding {
+        threshold: shader_config.dog_threshold,
+        tau: shader_config.tau,
+        phi: shader_config.phi,
+        _padding: 0.0,
     };
     let res_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&[resolution]),
+        contents: bytemuck::cast_slice(&[settings]),
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    let entries = [
+    // horizontal pass: desaturates the input and blurs it along x, for both blurs at once
+    let blur_target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("blur texture"),
+        size: size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let blur_view = blur_target.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let blur_entries = [
         wgpu::BindGroupEntry {
             binding: 0,
             resource: wgpu::BindingResource::TextureView(&texture_view),
@@ -52,9 +148,36 @@ pub fn new<'a>(
             binding: 1,
             resource: wgpu::BindingResource::Sampler(&sampler),
         },
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: blur_buffer.as_entire_binding(),
+        }
+    ];
+    let blur_bind_group = device.create_bind_group(
+        &wgpu::BindGroupDescriptor {
+            layout: &blur_pipeline.get_bind_group_layout(0),
+            entries: &blur_entries,
+            label: Some("DoG blur bind group"),
+        }
+    );
+
+    // vertical pass: finishes both blurs along y and subtracts them
+    let entries = [
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&blur_view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        },
         wgpu::BindGroupEntry {
             binding: 2,
             resource: res_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 3,
+            resource: blur_buffer.as_entire_binding(),
         }
     ];
     let bind_group = device.create_bind_group(
@@ -77,22 +200,12 @@ pub fn new<'a>(
         view_formats: &[],
     });
 
-    // output_buffer
-    let padded_width = 64 * (size.width as f32 / 64.0).ceil() as u32;
-    let read_buffer_size = utils::align_buffer_size(4 * padded_width * size.height, 256);
-    let read_buffer_desc = wgpu::BufferDescriptor {
-        size: read_buffer_size,
-        usage: wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::MAP_READ,
-        label: None,
-        mapped_at_creation: false,
-    };
-    let read_buffer = device.create_buffer(&read_buffer_desc);
-
     return DogShader {
+        blur_pipeline,
+        blur_bind_group,
+        blur_target,
         pipeline,
         bind_group,
         render_target,
-        output_buffer: read_buffer,
     }
-}
\ No newline at end of file
+}
@@ -1,13 +1,43 @@
 // downscale shader
 use wgpu::{util::DeviceExt};
-
-#[path = "../utils.rs"]
-mod utils;
-
+use super::utils;
+
+/// Glyphs picked by shape matching, in the order of `SHAPE_OCCUPANCY`
+pub const SHAPE_GLYPHS: &str = "|/\\_-=^v()<>oO";
+
+/// How much of each sub-region of a tile every shape glyph covers, in 4 rows of 3 
+/// columns from the top left. Must have `SHAPE_GLYPHS` entries, like the downscale wgsl files.
+const SHAPE_OCCUPANCY: [[f32; 12]; 14] = [
+    [0.0, 1.0, 0.0,  0.0, 1.0, 0.0,  0.0, 1.0, 0.0,  0.0, 1.0, 0.0], // |
+    [0.0, 0.0, 1.0,  0.0, 0.5, 0.5,  0.5, 0.5, 0.0,  1.0, 0.0, 0.0], // /
+    [1.0, 0.0, 0.0,  0.5, 0.5, 0.0,  0.0, 0.5, 0.5,  0.0, 0.0, 1.0], // \
+    [0.0, 0.0, 0.0,  0.0, 0.0, 0.0,  0.0, 0.0, 0.0,  1.0, 1.0, 1.0], // _
+    [0.0, 0.0, 0.0,  0.5, 0.5, 0.5,  0.5, 0.5, 0.5,  0.0, 0.0, 0.0], // -
+    [0.0, 0.0, 0.0,  1.0, 1.0, 1.0,  1.0, 1.0, 1.0,  0.0, 0.0, 0.0], // =
+    [0.5, 1.0, 0.5,  1.0, 0.0, 1.0,  0.0, 0.0, 0.0,  0.0, 0.0, 0.0], // ^
+    [0.0, 0.0, 0.0,  1.0, 0.0, 1.0,  0.5, 0.0, 0.5,  0.0, 1.0, 0.0], // v
+    [0.0, 1.0, 0.5,  1.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.5], // (
+    [0.5, 1.0, 0.0,  0.0, 0.0, 1.0,  0.0, 0.0, 1.0,  0.5, 1.0, 0.0], // )
+    [0.0, 0.5, 1.0,  1.0, 0.5, 0.0,  1.0, 0.5, 0.0,  0.0, 0.5, 1.0], // <
+    [1.0, 0.5, 0.0,  0.0, 0.5, 1.0,  0.0, 0.5, 1.0,  1.0, 0.5, 0.0], // >
+    [0.0, 0.0, 0.0,  1.0, 1.0, 1.0,  1.0, 0.0, 1.0,  1.0, 1.0, 1.0], // o
+    [1.0, 1.0, 1.0,  1.0, 0.0, 1.0,  1.0, 0.0, 1.0,  1.0, 1.0, 1.0], // O
+];
+
+/// Every uniform of the downscale shader, packed into one buffer. Must match 
+/// `Settings` in the downscale wgsl files.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct ResBinding {
+struct SettingsBinding {
     resolution: [f32; 2],
+    quantize: f32,
+    draw_edges: i32,
+    edge_threshold: f32,
+    dither: i32,
+    shape_glyphs: i32,
+    temporal: i32,
+    temporal_strength: f32,
+    transparent: i32,
 }
 
 #[repr(C)]
@@ -23,6 +53,9 @@ pub struct DownscaleShader<'a> {
     pub bind_group: wgpu::BindGroup,
     pub storage_buffer: wgpu::Buffer, // the compute "render_target"
     pub output_buffer: wgpu::Buffer,
+    // unquantized luminance of each tile, read back for error diffusion dithering
+    pub luma_storage_buffer: wgpu::Buffer,
+    pub luma_output_buffer: wgpu::Buffer,
 }
 
 pub struct DownscaleShaderStruct<'a> {
@@ -33,10 +66,15 @@ pub struct DownscaleShaderStruct<'a> {
     pub buffer_size: &'a u64,
     pub ascii_style: &'a str,
 
-    pub brightness: f32,
-    pub contrast: f32,
     pub draw_edges: bool,
     pub edge_threshold: f32,
+    pub bayer_dither: bool,
+    pub shape_glyphs: bool,
+    pub temporal: i32,
+    pub temporal_strength: f32,
+    pub transparent: bool,
+    // tiles of the last frame, shared with the other frames in flight
+    pub history_buffer: &'a wgpu::Buffer,
 }
 
 pub fn new<'a>(
@@ -53,45 +91,27 @@ pub fn new<'a>(
     let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
     let sobel_view = sobel_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    // buffers
-    let resolution = ResBinding {
+    // uniforms
+    let settings = SettingsBinding {
         resolution: [utils::align_buffer_size(size.width,64) as f32, size.height as f32],
+        quantize: desc.ascii_style.len() as f32,
+        draw_edges: if desc.draw_edges {1} else {0},
+        edge_threshold: desc.edge_threshold,
+        dither: if desc.bayer_dither {1} else {0},
+        shape_glyphs: if desc.shape_glyphs {1} else {0},
+        temporal: desc.temporal,
+        temporal_strength: desc.temporal_strength,
+        transparent: if desc.transparent {1} else {0},
     };
-    let res_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("res buffer"),
-        contents: bytemuck::cast_slice(&[resolution]),
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-    });
-
-    let quantize: f32 = desc.ascii_style.len() as f32;
-    let quant_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("quant buffer"),
-        contents: bytemuck::cast_slice(&[quantize]),
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("settings buffer"),
+        contents: bytemuck::cast_slice(&[settings]),
         usage: wgpu::BufferUsages::UNIFORM
     });
 
-    let brightness_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("brightness buffer"),
-        contents: bytemuck::cast_slice(&[desc.brightness]),
-        usage: wgpu::BufferUsages::UNIFORM
-    });
-    
-    let contrast_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("contrast buffer"),
-        contents: bytemuck::cast_slice(&[desc.contrast]),
-        usage: wgpu::BufferUsages::UNIFORM
-    });
-
-    let draw_edges = if desc.draw_edges == true {1} else {0};
-    let draw_edge_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("draw edge buffer"),
-        contents: bytemuck::cast_slice(&[draw_edges]),
-        usage: wgpu::BufferUsages::UNIFORM
-    });
-
-    let threshold_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("threshold buffer"),
-        contents: bytemuck::cast_slice(&[desc.edge_threshold]),
+    let glyphs_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("shape glyphs buffer"),
+        contents: bytemuck::cast_slice(&SHAPE_OCCUPANCY),
         usage: wgpu::BufferUsages::UNIFORM
     });
 
@@ -102,6 +122,12 @@ pub fn new<'a>(
         label: Some("compute source buffer"),
         mapped_at_creation: false,
     });
+    let luma_storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        size: buffer_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        label: Some("compute luma buffer"),
+        mapped_at_creation: false,
+    });
 
     let entries = [
         wgpu::BindGroupEntry {
@@ -118,29 +144,19 @@ pub fn new<'a>(
         },
         wgpu::BindGroupEntry {
             binding: 3,
-            resource: res_buffer.as_entire_binding(),
+            resource: settings_buffer.as_entire_binding(),
         },
         wgpu::BindGroupEntry {
             binding: 4,
-            resource: quant_buffer.as_entire_binding(),
+            resource: luma_storage_buffer.as_entire_binding(),
         },
-
-        // shader configs
         wgpu::BindGroupEntry {
             binding: 5,
-            resource: brightness_buffer.as_entire_binding(),
+            resource: glyphs_buffer.as_entire_binding(),
         },
         wgpu::BindGroupEntry {
             binding: 6,
-            resource: contrast_buffer.as_entire_binding(),
-        },
-        wgpu::BindGroupEntry {
-            binding: 7,
-            resource: draw_edge_buffer.as_entire_binding(),
-        },
-        wgpu::BindGroupEntry {
-            binding: 8,
-            resource: threshold_buffer.as_entire_binding(),
+            resource: desc.history_buffer.as_entire_binding(),
         },
     ];
     let bind_group = device.create_bind_group(
@@ -159,11 +175,14 @@ pub fn new<'a>(
         mapped_at_creation: false,
     };
     let read_buffer = device.create_buffer(&read_buffer_desc);
+    let luma_read_buffer = device.create_buffer(&read_buffer_desc);
 
     return DownscaleShader {
         pipeline,
         bind_group,
         storage_buffer,
         output_buffer: read_buffer,
+        luma_storage_buffer,
+        luma_output_buffer: luma_read_buffer,
     }
 }
\ No newline at end of file
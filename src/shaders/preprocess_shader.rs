@@ -0,0 +1,243 @@
+use wgpu::{util::DeviceExt};
+use super::utils::{Background, Equalize};
+
+// CLAHE splits the frame into CLAHE_GRID x CLAHE_GRID tiles, and caps every histogram bin
+// at CLAHE_CLIP_LIMIT times the average bin count
+const CLAHE_GRID: u32 = 8;
+const CLAHE_CLIP_LIMIT: f32 = 2.0;
+
+/// Must match `PreprocessSettings` in preprocess_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PreprocessSettingsBinding {
+    resolution: [f32; 2],
+    grid: [f32; 2],
+    brightness: f32,
+    contrast: f32,
+    gamma: f32,
+    saturation: f32,
+    sharpen: f32,
+    invert: i32,
+    equalize: i32,
+    _padding: f32,
+    background: [f32; 4],
+}
+
+pub struct PreprocessShader<'a> {
+    pub pipeline: &'a wgpu::RenderPipeline,
+    pub bind_group: wgpu::BindGroup,
+    pub render_target: wgpu::Texture,
+    pub lut_texture: wgpu::Texture,
+    equalize: Equalize,
+    grid: u32,
+    background: Background,
+}
+
+pub fn new<'a>(
+    device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Extent3d,
+    pipeline: &'a wgpu::RenderPipeline, shader_config: &super::utils::ShaderConfig,
+) -> PreprocessShader<'a> {
+
+    // bindgroup entries
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // equalization lookup tables, one row per tile. They are rewritten for every frame
+    let grid = match shader_config.equalize {
+        Equalize::Clahe => CLAHE_GRID,
+        _ => 1,
+    };
+    let lut_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("equalization lut"),
+        size: wgpu::Extent3d {
+            width: 256,
+            height: grid * grid,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let lut_view = lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // buffers
+    let settings = PreprocessSettingsBinding {
+        resolution: [size.width as f32, size.height as f32],
+        grid: [grid as f32, grid as f32],
+        brightness: shader_config.brightness,
+        contrast: shader_config.contrast,
+        gamma: shader_config.gamma,
+        saturation: shader_config.saturation,
+        sharpen: shader_config.sharpen,
+        invert: if shader_config.invert {1} else {0},
+        equalize: if shader_config.equalize == Equalize::None {0} else {1},
+        _padding: 0.0,
+        background: match shader_config.background {
+            Background::Color([r, g, b]) => [r, g, b, 1.0],
+            Background::Transparent => [0.0, 0.0, 0.0, 1.0],
+        },
+    };
+    let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("preprocess settings buffer"),
+        contents: bytemuck::cast_slice(&[settings]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let entries = [
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&texture_view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&lut_view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: settings_buffer.as_entire_binding(),
+        }
+    ];
+    let bind_group = device.create_bind_group(
+        &wgpu::BindGroupDescriptor {
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+            label: Some("preprocess bind group"),
+        }
+    );
+
+    // this is where the rendered output goes
+    let render_target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("preprocess texture"),
+        size: size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    return PreprocessShader {
+        pipeline,
+        bind_group,
+        render_target,
+        lut_texture,
+        equalize: shader_config.equalize,
+        grid,
+        background: shader_config.background,
+    }
+}
+
+impl PreprocessShader<'_> {
+    /// Builds the equalization tables for a frame and uploads them. Does nothing when
+    /// equalization is off.
+    pub fn write_lut(&self, queue: &wgpu::Queue, rgba: &image::RgbaImage) {
+        if self.equalize == Equalize::None {
+            return;
+        }
+
+        let mut histograms = luma_histograms(rgba, self.grid, self.background);
+        let mut lut = Vec::with_capacity(histograms.len() * 256);
+        for histogram in histograms.iter_mut() {
+            let pixel_count: u32 = histogram.iter().sum();
+            if self.equalize == Equalize::Clahe {
+                clip_histogram(histogram, pixel_count);
+            }
+
+            // the cumulative distribution is the lookup table
+            let mut cumulative = 0;
+            for bin in histogram.iter() {
+                cumulative += bin;
+                lut.push((255 * cumulative as u64 / std::cmp::max(pixel_count, 1) as u64) as u8);
+            }
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.lut_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &lut,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(256),
+                rows_per_image: Some(self.grid * self.grid),
+            },
+            self.lut_texture.size(),
+        );
+    }
+}
+
+/// The luma histogram of each of the grid x grid tiles of a frame. The shader equalizes 
+/// the source composited over the background, so the histograms are built from the 
+/// composited colors too. Over a transparent background, fully transparent pixels are not 
+/// drawn at all and are left out, as their hidden colors would skew the tables.
+fn luma_histograms(rgba: &image::RgbaImage, grid: u32, background: Background) -> Vec<[u32; 256]> {
+    let (width, height) = rgba.dimensions();
+    let (transparent, background) = match background {
+        Background::Transparent => (true, [0; 3]),
+        Background::Color(color) => (false, color.map(|channel| (channel * 255.0).round() as u32)),
+    };
+    let mut histograms = vec![[0u32; 256]; (grid * grid) as usize];
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        let alpha = pixel[3] as u32;
+        if transparent && alpha == 0 {
+            continue;
+        }
+        let channel = |i: usize| (pixel[i] as u32 * alpha + background[i] * (255 - alpha)) / 255;
+        let tile = (x * grid / width) + (y * grid / height) * grid;
+        // same luma weights as the shaders, in 8 bit fixed point
+        let luma = (54 * channel(0) + 183 * channel(1) + 19 * channel(2)) >> 8;
+        histograms[tile as usize][luma as usize] += 1;
+    }
+    return histograms;
+}
+
+/// Caps every bin at the clip limit and spreads the clipped counts evenly over all bins.
+/// This limits how much CLAHE can stretch the contrast of flat regions.
+fn clip_histogram(histogram: &mut [u32; 256], pixel_count: u32) {
+    let limit = std::cmp::max((CLAHE_CLIP_LIMIT * pixel_count as f32 / 256.0) as u32, 1);
+    let mut excess = 0;
+    for bin in histogram.iter_mut() {
+        if *bin > limit {
+            excess += *bin - limit;
+            *bin = limit;
+        }
+    }
+
+    let share = excess / 256;
+    let remainder = (excess % 256) as usize;
+    for (i, bin) in histogram.iter_mut().enumerate() {
+        *bin += share + if i < remainder {1} else {0};
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_of_transparent_frames() {
+        // a white half over fully transparent black
+        let rgba = image::RgbaImage::from_fn(4, 2, |x, _| {
+            if x < 2 {image::Rgba([255, 255, 255, 255])} else {image::Rgba([0, 0, 0, 0])}
+        });
+        let histograms = luma_histograms(&rgba, 1, Background::Transparent);
+        assert_eq!(histograms[0][255], 4);
+        assert_eq!(histograms[0].iter().sum::<u32>(), 4);
+
+        let histograms = luma_histograms(&rgba, 1, Background::Color([1.0, 1.0, 1.0]));
+        assert_eq!(histograms[0][255], 8);
+
+        // half transparent pixels are composited like in the shader
+        let rgba = image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 0, 128]));
+        let histograms = luma_histograms(&rgba, 2, Background::Color([1.0, 1.0, 1.0]));
+        for histogram in histograms.iter() {
+            assert_eq!(histogram[127], 1);
+        }
+    }
+}
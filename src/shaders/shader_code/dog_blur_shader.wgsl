@@ -0,0 +1,64 @@
+struct BlurSettings {
+    resolution: vec2f,
+    radius: i32,
+    weights: array<vec4f, 33>, // x: weak blur, y: strong blur, for each offset from the center
+}
+
+@group(0) @binding(0) var u_texture: texture_2d<f32>;
+@group(0) @binding(1) var u_sampler: sampler;
+@group(0) @binding(2) var<uniform> u_blur: BlurSettings;
+
+struct VertexShaderOutput {
+    @builtin(position) position: vec4f,
+    @location(0) frag_uv: vec2f,
+    @location(1) frag_coord: vec2f,
+};
+
+@vertex fn v_main(
+    @builtin(vertex_index) v_index : u32
+) -> VertexShaderOutput {
+    let pos = array(
+
+        vec2f( -1.0,  -1.0),  // bottom right
+        vec2f( -1.0,  1.0),  // top right
+        vec2f( 1.0,  -1.0),  // bottom right
+
+        vec2f( 1.0,  -1.0),  // bottom right
+        vec2f( -1.0,  1.0),  // top left
+        vec2f( 1.0,  1.0),  // top right
+    );
+
+    var v_output: VertexShaderOutput;
+    let xy = pos[v_index];
+    v_output.position = vec4f(xy.x, -xy.y, 0.0, 1.0);
+
+    v_output.frag_uv = (xy + 1) / 2; // convert clip-space (-1 - 1) to UV (0 - 1)
+    v_output.frag_coord = v_output.frag_uv * u_blur.resolution;
+
+    return v_output;
+}
+
+fn desaturate(color: vec3<f32>) -> f32 {
+    var lum = vec3(0.299, 0.587, 0.114);
+    return dot(lum, color);
+}
+
+// 1. horizontal half of the gaussian blurs. Desaturating first gives the same result as 
+// desaturating the blurs, since both are linear
+@fragment fn f_main(f_input: VertexShaderOutput) -> @location(0) vec4f {
+    var frag_coord = f_input.frag_coord;
+    var kSize = u_blur.radius;
+
+    var weakBlur = 0.0;
+    var strongBlur = 0.0;
+    for(var i = -kSize; i <= kSize; i++) {
+        var texel = textureSample(u_texture, u_sampler, (frag_coord + vec2(f32(i), 0.0)) / u_blur.resolution);
+        var luma = desaturate(texel.rgb);
+        var weights = u_blur.weights[abs(i)];
+
+        weakBlur += weights.x * luma;
+        strongBlur += weights.y * luma;
+    }
+
+    return vec4(weakBlur, strongBlur, 0.0, 1.0);
+}
@@ -1,5 +1,13 @@
-struct DogBinding {
+struct Settings {
     resolution: vec2f,
+    quantize: f32,
+    draw_edges: i32,
+    edge_threshold: f32,
+    dither: i32, // 1: bayer
+    shape_glyphs: i32,
+    temporal: i32, // 1: hysteresis, 2: exponential moving average
+    temporal_strength: f32,
+    transparent: i32, // 1: leave tiles without coverage blank
 }
 
 struct WorkgroupSize {
@@ -11,13 +19,10 @@ struct WorkgroupSize {
 @group(0) @binding(0) var<storage, read_write> storage_buffer: array<u32>;
 @group(0) @binding(1) var u_texture: texture_2d<f32>;
 @group(0) @binding(2) var u_sobel: texture_2d<f32>;
-@group(0) @binding(3) var<uniform> u_res: DogBinding;
-@group(0) @binding(4) var<uniform> u_quantize: f32;
-
-@group(0) @binding(5) var<uniform> u_brightness: f32;
-@group(0) @binding(6) var<uniform> u_contrast: f32;
-@group(0) @binding(7) var<uniform> u_draw_edges: i32;
-@group(0) @binding(8) var<uniform> u_edge_threshold: f32;
+@group(0) @binding(3) var<uniform> u_settings: Settings;
+@group(0) @binding(4) var<storage, read_write> luma_buffer: array<f32>; // unquantized tile luminance
+@group(0) @binding(5) var<uniform> u_glyphs: array<array<vec4f, 3>, SHAPE_GLYPHS>; // 3x4 occupancy of each shape glyph
+@group(0) @binding(6) var<storage, read_write> history: array<vec2f>; // x: luminance, y: glyph + 1 of the last frame
 
 fn vec4Equals(a: vec4<f32>, b: vec4<f32>) -> bool {
     var boolVec = a == b;
@@ -30,6 +35,7 @@ fn vec4Equals(a: vec4<f32>, b: vec4<f32>) -> bool {
 struct PixelData {
     luma: f32,
     edge_data: f32,
+    alpha: f32,
 }
 
 fn getLuma(tex: vec4<f32>) -> f32 {
@@ -37,20 +43,26 @@ fn getLuma(tex: vec4<f32>) -> f32 {
     return tex_luma;
 }
 
-fn quantize(luma: f32) -> f32 {
-    return round(luma * (u_quantize - 1.0));
-}
+// 4x4 ordered dithering thresholds
+const BAYER = array(0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+
+fn quantize(luma: f32, tile_id: vec2<u32>, last_glyph: f32) -> f32 {
+    var steps = u_settings.quantize - 1.0;
+    var offset = 0.0;
+    if(u_settings.dither == 1) {
+        // shifts the luminance by up to half a ramp step, in a fixed pattern across tiles
+        var bayer = BAYER;
+        var threshold = bayer[(tile_id.y % 4) * 4 + tile_id.x % 4];
+        offset = ((threshold + 0.5) / 16.0 - 0.5) / steps;
+    }
+    var level = (luma + offset) * steps;
 
-// TODO: this should be a pre-processing stage before DoG
-fn contrast(input: vec4<f32>) -> vec4<f32> {
-    var tex = input;
-    tex.r = mix(0.5, tex.r + u_brightness - 1.0, u_contrast);
-    tex.g = mix(0.5, tex.g + u_brightness - 1.0, u_contrast);
-    tex.b = mix(0.5, tex.b + u_brightness - 1.0, u_contrast);
-    tex.r = clamp(0.0, 1.0, tex.r);
-    tex.g = clamp(0.0, 1.0, tex.g);
-    tex.b = clamp(0.0, 1.0, tex.b);
-    return tex;
+    // keep the last level unless the new value moved past it by more than the margin
+    if(u_settings.temporal == 1 && last_glyph >= 0.0 && last_glyph < 1000.0
+        && abs(level - last_glyph) < 0.5 + u_settings.temporal_strength) {
+        return last_glyph;
+    }
+    return clamp(round(level), 0.0, steps);
 }
 
 // A designated file for each wg_size is gross, but I do not know of a better way
@@ -61,6 +73,108 @@ const y = f32(wg_y);
 const TILE_DIM = x * y;
 var<workgroup> tile: array<array<PixelData, wg_y>, wg_x>;
 
+const SHAPE_GLYPHS = 14;
+const SHAPE_BASE = 10000.0;
+// tiles where the source is (almost) fully transparent
+const TRANSPARENT = 5000.0;
+const ALPHA_CUTOFF = 0.05;
+
+// non-edge tiles only get a shape glyph when their regions differ by at least this much 
+// luminance, and when the glyph is at most this far from their pattern
+const SHAPE_CONTRAST = 0.25;
+const SHAPE_MAX_DISTANCE = 1.5;
+
+// the share of edge pixels in each 3x4 sub-region of the tile
+fn edgePattern() -> array<f32, 12> {
+    var occupancy = array<f32, 12>();
+    var counts = array<f32, 12>();
+    for(var i = 0; i < wg_x; i++) {
+        for(var j = 0; j < wg_y; j++) {
+            var region = (j * 4 / wg_y) * 3 + i * 3 / wg_x;
+            counts[region] += 1.0;
+            if(tile[i][j].edge_data > 0.0) {
+                occupancy[region] += 1.0;
+            }
+        }
+    }
+
+    // thin edge lines never fill a region, so the pattern is scaled to its fullest region
+    var peak = 0.0001;
+    for(var r = 0; r < 12; r++) {
+        occupancy[r] /= max(counts[r], 1.0);
+        peak = max(peak, occupancy[r]);
+    }
+    for(var r = 0; r < 12; r++) {
+        occupancy[r] /= peak;
+    }
+    return occupancy;
+}
+
+struct LumaPattern {
+    regions: array<f32, 12>,
+    contrast: f32,
+}
+
+// the average luminance of each 3x4 sub-region of the tile, scaled from its darkest (0) to 
+// its brightest region (1). Bright strokes on a dark background are the glyph's ink, like 
+// on the luminance ramp
+fn lumaPattern() -> LumaPattern {
+    var pattern = LumaPattern();
+    var counts = array<f32, 12>();
+    for(var i = 0; i < wg_x; i++) {
+        for(var j = 0; j < wg_y; j++) {
+            var region = (j * 4 / wg_y) * 3 + i * 3 / wg_x;
+            counts[region] += 1.0;
+            pattern.regions[region] += tile[i][j].luma;
+        }
+    }
+
+    var darkest = 1.0;
+    var brightest = 0.0;
+    for(var r = 0; r < 12; r++) {
+        pattern.regions[r] /= max(counts[r], 1.0);
+        darkest = min(darkest, pattern.regions[r]);
+        brightest = max(brightest, pattern.regions[r]);
+    }
+    pattern.contrast = brightest - darkest;
+    for(var r = 0; r < 12; r++) {
+        pattern.regions[r] = (pattern.regions[r] - darkest) / max(pattern.contrast, 0.0001);
+    }
+    return pattern;
+}
+
+// compares a 3x4 pattern of the tile against the occupancy of each shape glyph, and 
+// returns the index of the closest glyph and its distance. Glyphs are also tried one 
+// column to the left and right, since lines rarely line up with the center of a tile
+fn matchShape(pattern: array<f32, 12>, last_glyph: f32) -> vec2f {
+    var best = 0;
+    var best_distance = 999.0;
+    for(var g = 0; g < SHAPE_GLYPHS; g++) {
+        for(var shift = -1; shift <= 1; shift++) {
+            var distance = 0.0;
+            for(var r = 0; r < 12; r++) {
+                var col = r % 3 - shift;
+                var glyph = 0.0;
+                if(col >= 0 && col < 3) {
+                    var g_r = r - shift;
+                    glyph = u_glyphs[g][g_r / 4][g_r % 4];
+                }
+                var diff = pattern[r] - glyph;
+                distance += diff * diff;
+            }
+            // the last glyph is only replaced by a glyph that is closer by more than the margin
+            if(u_settings.temporal == 1 && f32(g) == last_glyph - SHAPE_BASE) {
+                distance -= u_settings.temporal_strength;
+            }
+            if(distance < best_distance) {
+                best_distance = distance;
+                best = g;
+            }
+        }
+    }
+    return vec2(f32(best), best_distance);
+}
+
 @compute @workgroup_size(wg_x, wg_y, 1)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
@@ -68,14 +182,14 @@ fn main(
     @builtin(workgroup_id) wg_id: vec3<u32>
 ) {
     var coords: vec2<i32> = vec2(i32(global_id.x), i32(global_id.y));
+    var f_id = vec2(f32(wg_id.x), f32(wg_id.y));
+    var index = i32(f_id.x + (f_id.y * ceil(u_settings.resolution.x/x)));
+
+    // read before the barrier below, so no invocation sees this frame's write
+    var last = history[index];
+    var last_glyph = last.y - 1.0; // -1 on the first frame
     var sobel = textureLoad(u_sobel, coords, 0);
-    var tex = textureLoad(u_texture, coords, 0);
-    if(tex.a < 0.01) {
-        tex.r *= tex.a;
-        tex.g *= tex.a;
-        tex.b *= tex.a;
-    }
-    tex = contrast(tex);
+    var tex = textureLoad(u_texture, coords, 0); // pre-processed source
 
     var edge_data = 0.0;
     var luma = 0.0;
@@ -98,6 +212,7 @@ fn main(
 
     tile[local_id.x][local_id.y].luma = luma;
     tile[local_id.x][local_id.y].edge_data = edge_data;
+    tile[local_id.x][local_id.y].alpha = tex.a;
 
     workgroupBarrier();
 
@@ -115,8 +230,8 @@ fn main(
 
     // if there are NO detected sobel gradients in a tile, then skip this step
     var res = 0.0;
-    var edge_threshold = u_edge_threshold;
-    if(u_draw_edges == 1) {
+    var edge_threshold = u_settings.edge_threshold;
+    if(u_settings.draw_edges == 1) {
         edge_threshold *= TILE_DIM;
     }
     else {
@@ -144,26 +259,56 @@ fn main(
         if(max < edge_threshold) {
             res = 0.0;
         }
-    }
 
-    // if no edges drawn, then calculate average brightness
-    if(res == 0.0) {
+        // keep the last edge direction while it still passes the threshold
+        if(u_settings.temporal == 1 && res > 0.0 && last_glyph >= 1000.0 && last_glyph <= 4000.0
+            && histogram[i32(last_glyph / 1000.0) - 1] >= edge_threshold) {
+            res = last_glyph;
+        }
+    }
 
-        var sum = 0.0;
-        for(var i = 0; i < wg_x; i++) {
-            for(var j = 0; j < wg_y; j++) {
-                sum += tile[i][j].luma;
+    if(u_settings.shape_glyphs == 1 && res > 0.0) {
+        res = SHAPE_BASE + matchShape(edgePattern(), last_glyph).x;
+    }
+    // tiles without edges can still hold a line or a curve, e.g. a thin stroke that is 
+    // too soft for the edge detection
+    else if(u_settings.shape_glyphs == 1) {
+        var pattern = lumaPattern();
+        if(pattern.contrast >= SHAPE_CONTRAST) {
+            var shape = matchShape(pattern.regions, last_glyph);
+            if(shape.y <= SHAPE_MAX_DISTANCE) {
+                res = SHAPE_BASE + shape.x;
             }
         }
-        res = quantize(sum/TILE_DIM);
-        
     }
- 
-    var coords_f = vec2(f32(coords.x), f32(coords.y));
 
-    var f_id = vec2(f32(wg_id.x), f32(wg_id.y));
+    // average brightness, smoothed over time if enabled
+    var sum = 0.0;
+    var alpha_sum = 0.0;
+    for(var i = 0; i < wg_x; i++) {
+        for(var j = 0; j < wg_y; j++) {
+            sum += tile[i][j].luma;
+            alpha_sum += tile[i][j].alpha;
+        }
+    }
+    var tile_luma = sum/TILE_DIM;
+    if(u_settings.temporal == 2 && last_glyph >= 0.0) {
+        tile_luma = mix(tile_luma, last.x, u_settings.temporal_strength);
+    }
+
+    // nothing is drawn where the terminal background should show through
+    if(u_settings.transparent == 1 && alpha_sum/TILE_DIM < ALPHA_CUTOFF) {
+        res = TRANSPARENT;
+    }
 
-    var index = f_id.x + (f_id.y * ceil(u_res.resolution.x/x));
+    // if no edges drawn, then use the average brightness
+    if(res == 0.0) {
+        res = quantize(tile_luma, wg_id.xy, last_glyph);
+    }
 
-    storage_buffer[i32(index)] = u32(res);
+    storage_buffer[index] = u32(res);
+    luma_buffer[index] = tile_luma;
+    if(local_id.x == 0 && local_id.y == 0) {
+        history[index] = vec2(tile_luma, res + 1.0);
+    }
 }
\ No newline at end of file
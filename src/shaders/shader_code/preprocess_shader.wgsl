@@ -0,0 +1,111 @@
+struct PreprocessSettings {
+    resolution: vec2f,
+    grid: vec2f, // equalization tiles along x and y
+    brightness: f32,
+    contrast: f32,
+    gamma: f32,
+    saturation: f32,
+    sharpen: f32,
+    invert: i32,
+    equalize: i32,
+    background: vec4f, // what transparent pixels are composited over
+}
+
+@group(0) @binding(0) var u_texture: texture_2d<f32>;
+@group(0) @binding(1) var u_lut: texture_2d<f32>; // one 256 entry row per equalization tile
+@group(0) @binding(2) var<uniform> u_settings: PreprocessSettings;
+
+struct VertexShaderOutput {
+    @builtin(position) position: vec4f,
+    @location(0) frag_uv: vec2f,
+    @location(1) frag_coord: vec2f,
+};
+
+@vertex fn v_main(
+    @builtin(vertex_index) v_index : u32
+) -> VertexShaderOutput {
+    let pos = array(
+
+        vec2f( -1.0,  -1.0),  // bottom right
+        vec2f( -1.0,  1.0),  // top right
+        vec2f( 1.0,  -1.0),  // bottom right
+
+        vec2f( 1.0,  -1.0),  // bottom right
+        vec2f( -1.0,  1.0),  // top left
+        vec2f( 1.0,  1.0),  // top right
+    );
+
+    var v_output: VertexShaderOutput;
+    let xy = pos[v_index];
+    v_output.position = vec4f(xy.x, -xy.y, 0.0, 1.0);
+
+    v_output.frag_uv = (xy + 1) / 2; // convert clip-space (-1 - 1) to UV (0 - 1)
+    v_output.frag_coord = v_output.frag_uv * u_settings.resolution;
+
+    return v_output;
+}
+
+fn getLuma(color: vec3<f32>) -> f32 {
+    return color.r * 0.2126 + color.g * 0.7152 + color.b * 0.0722;
+}
+
+fn lookup(tile: vec2<i32>, luma: i32) -> f32 {
+    var row = tile.x + tile.y * i32(u_settings.grid.x);
+    return textureLoad(u_lut, vec2(luma, row), 0).r;
+}
+
+// maps the luminance through the equalization tables of the 4 nearest tiles, and
+// blends between them so the tile borders don't show
+fn equalize(color: vec3<f32>, coords: vec2<f32>) -> vec3<f32> {
+    var luma = getLuma(color);
+    var index = i32(round(clamp(luma, 0.0, 1.0) * 255.0));
+
+    var grid = vec2<i32>(u_settings.grid);
+    var t = coords / (u_settings.resolution / u_settings.grid) - 0.5;
+    var t0 = clamp(vec2<i32>(floor(t)), vec2(0), grid - 1);
+    var t1 = min(t0 + 1, grid - 1);
+    var f = clamp(t - vec2<f32>(t0), vec2(0.0), vec2(1.0));
+
+    var top = mix(lookup(t0, index), lookup(vec2(t1.x, t0.y), index), f.x);
+    var bottom = mix(lookup(vec2(t0.x, t1.y), index), lookup(t1, index), f.x);
+    var equalized = mix(top, bottom, f.y);
+
+    return color * equalized / max(luma, 0.0001);
+}
+
+fn load(coords: vec2<i32>) -> vec3<f32> {
+    var size = vec2<i32>(u_settings.resolution);
+    var clamped = clamp(coords, vec2(0), size - 1);
+    var tex = textureLoad(u_texture, clamped, 0);
+    var color = mix(u_settings.background.rgb, tex.rgb, tex.a);
+    if(u_settings.equalize == 1) {
+        color = equalize(color, vec2<f32>(clamped) + 0.5);
+    }
+    return color;
+}
+
+// 0. adjusts the source before it goes to both the edge detection and the luminance pass.
+// The source alpha is passed through, so the downscale pass can find transparent tiles.
+@fragment fn f_main(f_input: VertexShaderOutput) -> @location(0) vec4f {
+    var coords = vec2<i32>(floor(f_input.frag_coord));
+    var alpha = textureLoad(u_texture, coords, 0).a;
+    var color = load(coords);
+
+    // unsharp mask
+    if(u_settings.sharpen > 0.0) {
+        var neighbours = load(coords + vec2(1, 0)) + load(coords - vec2(1, 0))
+            + load(coords + vec2(0, 1)) + load(coords - vec2(0, 1));
+        color += u_settings.sharpen * (color - neighbours / 4.0);
+    }
+
+    color = mix(vec3(0.5), color + u_settings.brightness - 1.0, u_settings.contrast);
+    color = clamp(color, vec3(0.0), vec3(1.0));
+    color = pow(color, vec3(1.0 / u_settings.gamma));
+    color = mix(vec3(getLuma(color)), color, u_settings.saturation);
+    if(u_settings.invert == 1) {
+        color = 1.0 - color;
+    }
+    color = clamp(color, vec3(0.0), vec3(1.0));
+
+    return vec4(color, alpha);
+}
@@ -1,10 +1,12 @@
-struct DogBinding {
+struct SobelSettings {
     resolution: vec2f,
+    kernel: vec2f, // outer and center weights of the sobel kernels
+    bin_width: f32, // half-width of the | and _ direction bins, in turns
 }
 
 @group(0) @binding(0) var u_texture: texture_2d<f32>;
 @group(0) @binding(1) var u_sampler: sampler;
-@group(0) @binding(2) var<uniform> u_res: DogBinding;
+@group(0) @binding(2) var<uniform> u_res: SobelSettings;
 
 struct VertexShaderOutput {
     @builtin(position) position: vec4f,
@@ -37,7 +39,6 @@ struct VertexShaderOutput {
 }
 
 const PI : f32 = 3.141592653589793238;
-const RADIAL_DIV : f32 = 1.0/16.0; // radial divider
 
 // honestly not sure why we're getting the luminance of a binary texel
 fn getFragLuma(offsetUV: vec2<f32>) -> f32 {
@@ -54,8 +55,8 @@ fn getFragLuma(offsetUV: vec2<f32>) -> f32 {
     var stepx = stepValue / u_res.resolution.x;
     var stepy = stepValue / u_res.resolution.y;
 
-    var kernel1 = 1.0;
-    var kernel2 = 2.0;
+    var kernel1 = u_res.kernel.x;
+    var kernel2 = u_res.kernel.y;
 
     var horizontalSobelMatrix = array<f32, 9>(
         -kernel1, 0.0, kernel1,
@@ -119,16 +120,17 @@ fn getFragLuma(offsetUV: vec2<f32>) -> f32 {
         // get gradient vector
         var t = atan2(gy, gx); // theta
         t = (t/PI) * 0.5 + 0.5; // normalize theta
-        var s = RADIAL_DIV;
+        var s = u_res.bin_width;
 
-        // quantize gradient vector to 4 different types
+        // quantize gradient vector to 4 different types. The diagonal bins take up 
+        // whatever is left between the horizontal and vertical ones
         // green
-        if((t >= s && t <= 3.0*s) || (t >= 0.5 + s && t <= 0.5 + 3.0*s)) {
+        if((t >= s && t <= 0.25 - s) || (t >= 0.5 + s && t <= 0.75 - s)) {
             color = green;
         }
 
         // yellow
-        if((t >= 0.25 + s && t <= 0.25 + 3.0*s) || (t >= 0.75 + s && t <= 0.75 + 3.0*s)) {
+        if((t >= 0.25 + s && t <= 0.5 - s) || (t >= 0.75 + s && t <= 1.0 - s)) {
             color = yellow;
         }
 
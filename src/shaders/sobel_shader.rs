@@ -1,22 +1,28 @@
 use wgpu::{util::DeviceExt};
 
-#[path = "../utils.rs"]
-mod utils;
+// outer and center weights of the sobel kernels
+const SOBEL_KERNEL: [f32; 2] = [1.0, 2.0];
 
+/// Must match `SobelSettings` in sobel_shader.wgsl
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct ResBinding {
+struct SobelSettingsBinding {
     resolution: [f32; 2],
+    kernel: [f32; 2],
+    bin_width: f32,
+    _padding: f32,
 }
 
 pub struct SobelShader<'a> {
     pub pipeline: &'a wgpu::RenderPipeline,
     pub bind_group: wgpu::BindGroup,
     pub render_target: wgpu::Texture,
-    pub output_buffer: wgpu::Buffer,
 }
 
-pub fn new<'a>(device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Extent3d, pipeline: &'a wgpu::RenderPipeline) -> SobelShader<'a> {
+pub fn new<'a>(
+    device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Extent3d,
+    pipeline: &'a wgpu::RenderPipeline, shader_config: &super::utils::ShaderConfig,
+) -> SobelShader<'a> {
 
     // bindgroup entries
     let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -31,12 +37,15 @@ pub fn new<'a>(device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Exten
     });
 
     // buffers
-    let resolution = ResBinding {
+    let settings = SobelSettingsBinding {
         resolution: [size.width as f32, size.height as f32],
+        kernel: SOBEL_KERNEL,
+        bin_width: shader_config.edge_bin_width,
+        _padding: 0.0,
     };
     let res_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: None,
-        contents: bytemuck::cast_slice(&[resolution]),
+        contents: bytemuck::cast_slice(&[settings]),
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
@@ -58,7 +67,7 @@ pub fn new<'a>(device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Exten
         &wgpu::BindGroupDescriptor {
             layout: &pipeline.get_bind_group_layout(0),
             entries: &entries,
-            label: Some("DoG bind group"),
+            label: Some("sobel bind group"),
         }
     );
 
@@ -74,24 +83,9 @@ pub fn new<'a>(device: &wgpu::Device, texture: &wgpu::Texture, size: wgpu::Exten
         view_formats: &[],
     });
 
-    // output_buffer
-
-    // let u32_size = std::mem::size_of::<u32>() as u32; // u32 size = 4, no need to be fancy
-    let padded_width = 64 * (size.width as f32 / 64.0).ceil() as u32;
-    let read_buffer_size = utils::align_buffer_size(4 * padded_width * size.height, 256);
-    let read_buffer_desc = wgpu::BufferDescriptor {
-        size: read_buffer_size,
-        usage: wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::MAP_READ,
-        label: None,
-        mapped_at_creation: false,
-    };
-    let read_buffer = device.create_buffer(&read_buffer_desc);
-
     return SobelShader {
         pipeline,
         bind_group,
         render_target,
-        output_buffer: read_buffer,
     }
 }
\ No newline at end of file
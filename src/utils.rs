@@ -52,9 +52,147 @@ pub fn create_render_pipeline_desc(module: &wgpu::ShaderModule) -> wgpu::RenderP
     return pipeline_desc;
 }
 
+/// Histogram equalization mode of the pre-process pass
+#[derive(Clone, Copy, PartialEq)]
+pub enum Equalize {
+    None,
+    Global,
+    Clahe,
+}
+
+impl std::str::FromStr for Equalize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "none" => Ok(Equalize::None),
+            "global" => Ok(Equalize::Global),
+            "clahe" => Ok(Equalize::Clahe),
+            _ => Err(format!("unknown equalization mode \"{}\" (expected none, global or clahe)", s)),
+        };
+    }
+}
+
+/// Dithering of the luminance ramp. Bayer runs in the downscale shader, the error 
+/// diffusion modes run on the CPU once the tiles are read back.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dither {
+    None,
+    Bayer,
+    FloydSteinberg,
+    Atkinson,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "none" => Ok(Dither::None),
+            "bayer" => Ok(Dither::Bayer),
+            "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            "atkinson" => Ok(Dither::Atkinson),
+            _ => Err(format!("unknown dither mode \"{}\" (expected none, bayer, floyd-steinberg or atkinson)", s)),
+        };
+    }
+}
+
+/// Temporal filter applied to the tiles of videos. The values match `temporal` in the 
+/// downscale wgsl files.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Temporal {
+    None = 0,
+    Hysteresis = 1,
+    Ema = 2,
+}
+
+impl std::str::FromStr for Temporal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "none" => Ok(Temporal::None),
+            "hysteresis" => Ok(Temporal::Hysteresis),
+            "ema" => Ok(Temporal::Ema),
+            _ => Err(format!("unknown temporal filter \"{}\" (expected none, hysteresis or ema)", s)),
+        };
+    }
+}
+
+/// The luminance ramp, from the darkest to the brightest glyph. Glyphs are cached as single 
+/// bytes, so only printable ASCII is supported.
+#[derive(Clone, PartialEq)]
+pub struct Charset(pub String);
+
+impl std::str::FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+            return Err(format!("invalid charset \"{}\" (only printable ASCII characters are supported)", s));
+        }
+        if !(2..=64).contains(&s.len()) {
+            return Err(format!("invalid charset \"{}\" (expected 2 to 64 characters)", s));
+        }
+        return Ok(Charset(String::from(s)));
+    }
+}
+
+/// What the transparent parts of the source are composited over. With `Transparent` the 
+/// source is composited over black, and tiles without any coverage are left blank.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Background {
+    Transparent,
+    Color([f32; 3]),
+}
+
+impl std::str::FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        return match s {
+            "transparent" => Ok(Background::Transparent),
+            "black" => Ok(Background::Color([0.0, 0.0, 0.0])),
+            "white" => Ok(Background::Color([1.0, 1.0, 1.0])),
+            _ if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as f32 / 255.0;
+                Ok(Background::Color([channel(0), channel(2), channel(4)]))
+            }
+            _ => Err(format!("unknown background \"{}\" (expected transparent, black, white or #rrggbb)", s)),
+        };
+    }
+}
+
+#[derive(Clone)]
 pub struct ShaderConfig {
+    // pre-process
     pub brightness: f32,
     pub contrast: f32,
+    pub gamma: f32,
+    pub saturation: f32,
+    pub invert: bool,
+    pub equalize: Equalize,
+    pub sharpen: f32,
+    pub background: Background,
+
+    pub charset: Charset,
     pub draw_edges: bool,
     pub edge_threshold: f32,
+    pub dither: Dither,
+    pub shape_glyphs: bool,
+    pub temporal: Temporal,
+    pub temporal_strength: f32,
+
+    // DoG (edge detection)
+    pub sigma: f32,
+    pub sigma_ratio: f32,
+    /// `None` blurs up to 3 sigma of the strong blur
+    pub kernel_radius: Option<u32>,
+    pub dog_threshold: f32,
+    pub tau: f32,
+    pub phi: f32,
+
+    // sobel (edge direction)
+    pub edge_bin_width: f32,
 }
\ No newline at end of file